@@ -0,0 +1,112 @@
+// what to do once the child process exits
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ExitPolicy {
+    #[default]
+    Close,
+    Hold,
+    Respawn,
+}
+// what programs may do with the clipboard through OSC 52
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Osc52Policy {
+    Deny,
+    Copy,
+    // reading lets any output, such as a file being viewed, take the clipboard
+    CopyAndPaste,
+}
+#[derive(Clone, Debug)]
+pub struct Config {
+    // program and arguments to run instead of the user's shell
+    pub command: Option<Vec<String>>,
+    // start the shell as a login shell
+    pub login_shell: bool,
+    pub exit_policy: ExitPolicy,
+    // number of lines kept after they scroll off the top of the screen
+    pub scrollback_lines: usize,
+    // Alt (Option on macOS) sends an ESC before the key instead of composing
+    // characters
+    pub option_as_meta: bool,
+    // characters that end a word for double click selection
+    pub word_separators: String,
+    // ask before pasting several lines into a program without bracketed paste
+    pub confirm_paste: bool,
+    pub osc52: Osc52Policy,
+    // the largest text in bytes OSC 52 may copy or paste
+    pub osc52_limit: usize,
+    // the window title until a program sets one, {program} is replaced by
+    // the name of the program that was started
+    pub title: String,
+}
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            command: None,
+            login_shell: false,
+            exit_policy: ExitPolicy::default(),
+            scrollback_lines: 10000,
+            option_as_meta: !cfg!(target_os = "macos"),
+            word_separators: ",│`|:\"' ()[]{}<>\t".to_string(),
+            confirm_paste: false,
+            osc52: Osc52Policy::Copy,
+            osc52_limit: 1 << 20,
+            title: "{program} - quad".to_string(),
+        }
+    }
+}
+impl Config {
+    pub fn from_args() -> Config {
+        let mut config = Config::default();
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-l" | "--login" => config.login_shell = true,
+                "--on-exit" => match args.next().as_deref() {
+                    Some("close") => config.exit_policy = ExitPolicy::Close,
+                    Some("hold") => config.exit_policy = ExitPolicy::Hold,
+                    Some("respawn") => config.exit_policy = ExitPolicy::Respawn,
+                    other => eprintln!("--on-exit expects close, hold or respawn, got {:?}", other),
+                },
+                "--scrollback" => match args.next().map(|lines| lines.parse()) {
+                    Some(Ok(lines)) => config.scrollback_lines = lines,
+                    _ => eprintln!("--scrollback expects a number of lines"),
+                },
+                "--option-as-meta" => match args.next().as_deref() {
+                    Some("on") => config.option_as_meta = true,
+                    Some("off") => config.option_as_meta = false,
+                    other => eprintln!("--option-as-meta expects on or off, got {:?}", other),
+                },
+                "--confirm-paste" => match args.next().as_deref() {
+                    Some("on") => config.confirm_paste = true,
+                    Some("off") => config.confirm_paste = false,
+                    other => eprintln!("--confirm-paste expects on or off, got {:?}", other),
+                },
+                "--osc52" => match args.next().as_deref() {
+                    Some("deny") => config.osc52 = Osc52Policy::Deny,
+                    Some("copy") => config.osc52 = Osc52Policy::Copy,
+                    Some("copy-paste") => config.osc52 = Osc52Policy::CopyAndPaste,
+                    other => eprintln!("--osc52 expects deny, copy or copy-paste, got {:?}", other),
+                },
+                "--osc52-limit" => match args.next().map(|bytes| bytes.parse()) {
+                    Some(Ok(bytes)) => config.osc52_limit = bytes,
+                    _ => eprintln!("--osc52-limit expects a number of bytes"),
+                },
+                "--title" => match args.next() {
+                    Some(title) => config.title = title,
+                    None => eprintln!("--title expects a title"),
+                },
+                "--word-separators" => match args.next() {
+                    Some(separators) => config.word_separators = separators,
+                    None => eprintln!("--word-separators expects a list of characters"),
+                },
+                "-e" | "--command" => {
+                    let command: Vec<String> = args.by_ref().collect();
+                    if !command.is_empty() {
+                        config.command = Some(command);
+                    }
+                }
+                _ => eprintln!("unknown argument: {}", arg),
+            }
+        }
+        config
+    }
+}
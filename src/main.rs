@@ -26,14 +26,20 @@ pub fn wasm_main() {
     std::panic::set_hook(Box::new(console_error_panic_hook::hook));
     main();
 }
+mod config;
 mod renderer;
 mod text_render;
 use gfx_hal::{prelude::*, window};
 use renderer::Renderer;
 pub use renderer::Updater;
+#[derive(Debug, Clone, Copy)]
+pub enum UserEvent {
+    // the child process wrote something or exited
+    ProcessOutput,
+}
 
 fn main() {
-    let mut text_render = text_render::TextRender::new(renderer::DIMS.width, renderer::DIMS.height);
+    let config = config::Config::from_args();
     #[cfg(target_arch = "wasm32")]
     console_log::init_with_level(log::Level::Debug).unwrap();
 
@@ -51,7 +57,13 @@ fn main() {
         "You are running the example with the empty backend, no graphical output is to be expected"
     );
 
-    let event_loop = winit::event_loop::EventLoop::new();
+    let event_loop = winit::event_loop::EventLoop::<UserEvent>::with_user_event();
+    let mut text_render = text_render::TextRender::new(
+        renderer::DIMS.width,
+        renderer::DIMS.height,
+        &config,
+        event_loop.create_proxy(),
+    );
 
     let wb = winit::window::WindowBuilder::new()
         .with_min_inner_size(winit::dpi::Size::Logical(winit::dpi::LogicalSize::new(
@@ -61,11 +73,11 @@ fn main() {
             renderer::DIMS.width,
             renderer::DIMS.height,
         )))
-        .with_title("quad".to_string());
+        .with_title(text_render.take_title().unwrap_or_default());
 
     // instantiate backend
     #[cfg(not(target_arch = "wasm32"))]
-    let (_window, instance, mut adapters, surface) = {
+    let (window, instance, mut adapters, surface) = {
         let window = wb.build(&event_loop).unwrap();
         let instance =
             back::Instance::create("gfx-rs quad", 1).expect("Failed to create an instance!");
@@ -80,7 +92,7 @@ fn main() {
     };
 
     #[cfg(target_arch = "wasm32")]
-    let (_window, instance, mut adapters, surface) = {
+    let (window, instance, mut adapters, surface) = {
         let (window, surface) = {
             let window = wb.build(&event_loop).unwrap();
             web_sys::window()
@@ -122,11 +134,31 @@ fn main() {
                 winit::event::WindowEvent::KeyboardInput {
                     input:
                         winit::event::KeyboardInput {
-                            virtual_keycode, ..
+                            virtual_keycode,
+                            state,
+                            ..
                         },
                     ..
                 } => {
-                    text_render.update(virtual_keycode);
+                    text_render.update(virtual_keycode, state);
+                }
+                winit::event::WindowEvent::Focused(focused) => {
+                    text_render.focused(focused);
+                }
+                winit::event::WindowEvent::ReceivedCharacter(c) => {
+                    text_render.received_character(c);
+                }
+                winit::event::WindowEvent::ModifiersChanged(modifiers) => {
+                    text_render.set_modifiers(modifiers);
+                }
+                winit::event::WindowEvent::MouseWheel { delta, .. } => {
+                    text_render.mouse_wheel(delta);
+                }
+                winit::event::WindowEvent::CursorMoved { position, .. } => {
+                    text_render.cursor_moved(position);
+                }
+                winit::event::WindowEvent::MouseInput { state, button, .. } => {
+                    text_render.mouse_input(state, button);
                 }
 
                 winit::event::WindowEvent::Resized(dims) => {
@@ -136,11 +168,22 @@ fn main() {
                         height: dims.height,
                     };
                     renderer.recreate_swapchain();
+                    text_render.resize(dims.width, dims.height);
                 }
                 _ => {}
             },
+            // waking the loop is enough, the new output is drawn on RedrawEventsCleared
+            winit::event::Event::UserEvent(UserEvent::ProcessOutput) => {}
             winit::event::Event::RedrawEventsCleared => {
                 renderer.render(&mut text_render);
+                if let Some(title) = text_render.take_title() {
+                    window.set_title(&title);
+                }
+                if text_render.should_close() {
+                    *control_flow = winit::event_loop::ControlFlow::Exit
+                } else if let Some(time) = text_render.next_redraw() {
+                    *control_flow = winit::event_loop::ControlFlow::WaitUntil(time)
+                }
             }
             _ => {}
         }
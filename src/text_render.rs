@@ -1,108 +1,749 @@
-use font_kit::canvas::{Canvas, Format, RasterizationOptions};
+use crate::config::{Config, ExitPolicy, Osc52Policy};
+use crate::UserEvent;
 use font_kit::family_name::FamilyName;
-use font_kit::hinting::HintingOptions;
 use font_kit::properties::Properties;
 use font_kit::source::SystemSource;
-use image::buffer::ConvertBuffer;
-use pathfinder_geometry::transform2d::Transform2F;
-use pathfinder_geometry::vector::{Vector2F, Vector2I};
+use mouse::{MouseAction, MouseEncoding, MouseMode, MouseReport};
+use std::time::{Duration, Instant};
+use winit::dpi::PhysicalPosition;
+use winit::event::{ElementState, ModifiersState, MouseButton, MouseScrollDelta, VirtualKeyCode};
+use winit::event_loop::EventLoopProxy;
+mod clipboard;
+mod color;
+mod glyph;
+mod grid;
+mod keyboard;
+mod mouse;
+mod parser;
+mod paste;
+// the child process runs on a pseudo terminal, which only unix has
+#[cfg(unix)]
 mod process;
+#[cfg(not(unix))]
+#[path = "text_render/no_process.rs"]
+mod process;
+#[cfg(unix)]
+mod pty;
+mod scrollback;
+mod selection;
+mod term;
+mod utf8;
+use clipboard::{Clipboard, ClipboardKind};
+use glyph::GlyphCache;
+use grid::{Cell, Color, Flags};
+use selection::{Point, Selection, SelectionKind};
+use term::{ClipboardRequest, Term};
+const FONT_SIZE: f32 = 12.0;
+const BLINK_INTERVAL: Duration = Duration::from_millis(500);
+// clicks on the same cell within this time select words and then lines
+const MULTI_CLICK_INTERVAL: Duration = Duration::from_millis(400);
+// a process that exits sooner than this counts as a quick exit,
+// RESPAWN_LIMIT of them in a row stop the respawning
+const RESPAWN_MIN_RUNTIME: Duration = Duration::from_secs(1);
+const RESPAWN_LIMIT: usize = 5;
+// the terminal size in cells and the window size in pixels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WindowSize {
+    pub cols: u16,
+    pub rows: u16,
+    pub pixel_width: u16,
+    pub pixel_height: u16,
+}
+// Size of one character cell in pixels, every glyph is drawn into a cell of
+// this size.
+#[derive(Clone, Copy, Debug)]
+pub struct CellMetrics {
+    pub width: f32,
+    pub height: f32,
+    pub baseline: f32,
+}
+impl CellMetrics {
+    fn new(font: &font_kit::font::Font) -> CellMetrics {
+        let metrics = font.metrics();
+        let scale = FONT_SIZE / metrics.units_per_em as f32;
+        let advance = font
+            .glyph_for_char('M')
+            .and_then(|glyph| font.advance(glyph).ok())
+            .map(|advance| advance.x())
+            .unwrap_or(metrics.units_per_em as f32 / 2.0);
+        CellMetrics {
+            width: (advance * scale).ceil(),
+            height: ((metrics.ascent - metrics.descent + metrics.line_gap) * scale).ceil(),
+            baseline: (metrics.ascent * scale).ceil(),
+        }
+    }
+    // number of whole cells that fit in a window, never less than one
+    pub fn window_size(&self, x: u32, y: u32) -> WindowSize {
+        WindowSize {
+            cols: ((x as f32 / self.width) as u16).max(1),
+            rows: ((y as f32 / self.height) as u16).max(1),
+            pixel_width: x as u16,
+            pixel_height: y as u16,
+        }
+    }
+    // the column and line of the cell under a pixel, clamped to the grid
+    pub fn cell_at(&self, position: PhysicalPosition<f64>, size: WindowSize) -> (usize, usize) {
+        let col = (position.x.max(0.0) / self.width as f64) as usize;
+        let line = (position.y.max(0.0) / self.height as f64) as usize;
+        (
+            col.min(size.cols as usize - 1),
+            line.min(size.rows as usize - 1),
+        )
+    }
+}
 pub struct TextRender {
-    font: font_kit::loaders::directwrite::Font,
-    canvas: Canvas,
-    buffer: Vec<char>,
-    process: process::ProcessManager,
+    glyphs: GlyphCache,
+    cell: CellMetrics,
+    size: WindowSize,
+    term: Term,
+    process: Option<process::ProcessManager>,
+    config: Config,
+    proxy: EventLoopProxy<UserEvent>,
+    exited: bool,
+    should_close: bool,
+    // when the current process was started, and how many processes in a row
+    // exited sooner than RESPAWN_MIN_RUNTIME
+    spawned: Instant,
+    quick_exits: usize,
+    started: Instant,
+    // whether the last frame drew blinking text
+    blinking: bool,
+    modifiers: ModifiersState,
+    keyboard: keyboard::Keyboard,
+    mouse_position: PhysicalPosition<f64>,
+    // the cell under the mouse pointer
+    mouse_cell: (usize, usize),
+    mouse_held: Option<MouseButton>,
+    clipboard: Clipboard,
+    selection: Option<Selection>,
+    // the left button is down and moving the mouse changes the selection
+    selecting: bool,
+    // when and where the left button was last pressed, and how many clicks
+    // in a row that was
+    last_click: Option<(Instant, Point)>,
+    clicks: usize,
+    // multi-line text waiting for the paste to be confirmed
+    pending_paste: Option<String>,
 }
 
 impl TextRender {
-    pub fn new(x: u32, y: u32) -> TextRender {
+    pub fn new(x: u32, y: u32, config: &Config, proxy: EventLoopProxy<UserEvent>) -> TextRender {
         println!("x: {} y: {}", x, y);
-        TextRender {
-            font: SystemSource::new()
-                .select_best_match(&[FamilyName::Monospace], &Properties::new())
-                .unwrap()
-                .load()
-                .unwrap(),
-            canvas: Canvas::new(Vector2I::new(x as i32, y as i32), Format::Rgb24),
-            buffer: vec![],
-            process: process::ProcessManager::new(),
-        }
-    }
-    pub fn update(&mut self, key: Option<winit::event::VirtualKeyCode>) {
-        if let Some(key) = key {
-            let c = key_code_to_char(key);
-            let mut str = String::new();
-            str.push(c);
-            self.process.write(str);
-            self.buffer.push(c);
+        let font = SystemSource::new()
+            .select_best_match(&[FamilyName::Monospace], &Properties::new())
+            .unwrap()
+            .load()
+            .unwrap();
+        let cell = CellMetrics::new(&font);
+        let size = cell.window_size(x, y);
+        let mut text_render = TextRender {
+            glyphs: GlyphCache::new(font, cell),
+            cell,
+            size,
+            term: Term::new(
+                size.cols as usize,
+                size.rows as usize,
+                config.scrollback_lines,
+            ),
+            process: None,
+            config: config.clone(),
+            proxy,
+            exited: false,
+            should_close: false,
+            spawned: Instant::now(),
+            quick_exits: 0,
+            started: Instant::now(),
+            blinking: false,
+            modifiers: ModifiersState::empty(),
+            keyboard: keyboard::Keyboard::default(),
+            mouse_position: PhysicalPosition::new(0.0, 0.0),
+            mouse_cell: (0, 0),
+            mouse_held: None,
+            clipboard: Clipboard::new(),
+            selection: None,
+            selecting: false,
+            last_click: None,
+            clicks: 0,
+            pending_paste: None,
+        };
+        text_render
+            .term
+            .set_cell_size(cell.width as usize, cell.height as usize);
+        text_render.spawn();
+        text_render
+    }
+    fn spawn(&mut self) {
+        self.exited = false;
+        self.spawned = Instant::now();
+        self.process =
+            match process::ProcessManager::new(&self.config, self.size, self.proxy.clone()) {
+                Ok(process) => Some(process),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    self.term.advance(e.to_string().as_bytes());
+                    None
+                }
+            };
+    }
+    // the new window title when it changed since the last call
+    pub fn take_title(&mut self) -> Option<String> {
+        if self.term.take_title_changed() {
+            Some(self.title())
+        } else {
+            None
         }
     }
-}
-fn key_code_to_char(key: winit::event::VirtualKeyCode) -> char {
-    use winit::event::VirtualKeyCode::*;
-    match key {
-        A => 'a',
-        B => 'b',
-        C => 'c',
-        D => 'd',
-        E => 'e',
-        F => 'f',
-        G => 'g',
-        H => 'h',
-        I => 'i',
-        J => 'j',
-        K => 'k',
-        L => 'l',
-        M => 'm',
-        N => 'n',
-        O => 'o',
-        P => 'q',
-        R => 'r',
-        S => 's',
-        T => 'q',
-        Q => 'q',
-        U => 'u',
-        V => 'v',
-        X => 'x',
-        Y => 'y',
-        Z => 'z',
-        _ => '*',
+    // the title set by the program, or the configured one
+    fn title(&self) -> String {
+        if let Some(title) = &self.term.title().title {
+            return title.clone();
+        }
+        let program = self
+            .process
+            .as_ref()
+            .map_or("", |process| process.program());
+        let name = std::path::Path::new(program)
+            .file_name()
+            .map_or(program.into(), |name| name.to_string_lossy());
+        self.config.title.replace("{program}", &name)
+    }
+    pub fn should_close(&self) -> bool {
+        self.should_close
+    }
+    // when blinking text next changes phase and needs a redraw
+    pub fn next_redraw(&self) -> Option<Instant> {
+        if !self.blinking {
+            return None;
+        }
+        let elapsed = self.started.elapsed().as_millis() / BLINK_INTERVAL.as_millis();
+        Some(self.started + BLINK_INTERVAL * (elapsed as u32 + 1))
+    }
+    fn read_process(&mut self) {
+        let output = match &mut self.process {
+            Some(process) => process.read(),
+            None => return,
+        };
+        self.term.advance(&output);
+        let mut responses = self.term.take_responses();
+        // clipboard replies are put in place afterwards, programs often check
+        // for OSC 52 by following it with a query that is always answered
+        let mut replies = vec![];
+        for request in self.term.take_clipboard_requests() {
+            replies.extend(self.clipboard_request(request));
+        }
+        for (at, reply) in replies.into_iter().rev() {
+            responses.insert_str(at, &reply);
+        }
+        if !responses.is_empty() {
+            self.write(responses.as_bytes());
+        }
+    }
+    // the reply to a read and where it goes in the responses
+    fn clipboard_request(&mut self, request: ClipboardRequest) -> Option<(usize, String)> {
+        let policy = self.config.osc52;
+        let limit = self.config.osc52_limit;
+        match request {
+            ClipboardRequest::Store(kind, text) => {
+                if policy == Osc52Policy::Deny {
+                    return None;
+                }
+                if text.len() > limit {
+                    eprintln!("ignored OSC 52 copy of {} bytes", text.len());
+                    return None;
+                }
+                self.clipboard.store(kind, text);
+                None
+            }
+            ClipboardRequest::Load(kind, selection, at) => {
+                if policy != Osc52Policy::CopyAndPaste {
+                    return None;
+                }
+                let text = self.clipboard.load(kind);
+                if text.len() > limit {
+                    eprintln!("ignored OSC 52 paste of {} bytes", text.len());
+                    return None;
+                }
+                let reply = format!("\x1b]52;{};{}\x1b\\", selection, base64::encode(text));
+                Some((at, reply))
+            }
+        }
+    }
+    fn check_exit(&mut self) {
+        if self.exited {
+            return;
+        }
+        let status = match self.process.as_mut().and_then(|p| p.exit_status()) {
+            Some(status) => status,
+            None => return,
+        };
+        self.exited = true;
+        // pick up anything written right before the exit
+        self.read_process();
+        if self.spawned.elapsed() < RESPAWN_MIN_RUNTIME {
+            self.quick_exits += 1;
+        } else {
+            self.quick_exits = 0;
+        }
+        let status = match status.code() {
+            Some(code) => format!("status {}", code),
+            None => status.to_string(),
+        };
+        match self.config.exit_policy {
+            ExitPolicy::Close => self.should_close = true,
+            ExitPolicy::Hold => {
+                let banner = format!("\r\n[process exited with {}]", status);
+                self.term.advance(banner.as_bytes());
+            }
+            // a program that keeps failing straight away is left on screen
+            // instead of being restarted forever
+            ExitPolicy::Respawn if self.quick_exits >= RESPAWN_LIMIT => {
+                let banner = format!(
+                    "\r\n[process exited with {}, not restarting after {} quick exits]",
+                    status, self.quick_exits
+                );
+                self.term.advance(banner.as_bytes());
+            }
+            ExitPolicy::Respawn => {
+                // the new process starts on a fresh screen with default modes
+                self.term = Term::new(
+                    self.size.cols as usize,
+                    self.size.rows as usize,
+                    self.config.scrollback_lines,
+                );
+                self.term
+                    .set_cell_size(self.cell.width as usize, self.cell.height as usize);
+                self.selection = None;
+                self.selecting = false;
+                self.pending_paste = None;
+                self.spawn();
+            }
+        }
+    }
+    pub fn resize(&mut self, x: u32, y: u32) {
+        let size = self.cell.window_size(x, y);
+        if size == self.size {
+            return;
+        }
+        self.size = size;
+        // reflowing moves the text the selection was on
+        self.selection = None;
+        self.term.resize(size.cols as usize, size.rows as usize);
+        if let Some(process) = &mut self.process {
+            process.resize(size);
+        }
+    }
+    pub fn focused(&mut self, focused: bool) {
+        if !focused {
+            self.keyboard.clear();
+        }
+    }
+    pub fn set_modifiers(&mut self, modifiers: ModifiersState) {
+        self.modifiers = modifiers;
+    }
+    pub fn mouse_wheel(&mut self, delta: MouseScrollDelta) {
+        let lines = match delta {
+            MouseScrollDelta::LineDelta(_, y) => (y * 3.0) as isize,
+            MouseScrollDelta::PixelDelta(position) => {
+                (position.y / self.cell.height as f64) as isize
+            }
+        };
+        if !self.mouse_reporting() {
+            if !self.term.alt_screen() {
+                return self.term.scroll_display(lines);
+            }
+            // programs on the alternate screen, such as pagers, can ask to
+            // scroll with the cursor keys
+            if !self.term.alternate_scroll() {
+                return;
+            }
+            let key = if lines > 0 {
+                VirtualKeyCode::Up
+            } else {
+                VirtualKeyCode::Down
+            };
+            let modes = self.term.key_modes();
+            if let Some(sequence) = keyboard::encode(key, ModifiersState::empty(), modes) {
+                self.send(sequence.repeat(lines.unsigned_abs()).as_bytes());
+            }
+            return;
+        }
+        let action = if lines > 0 {
+            MouseAction::WheelUp
+        } else {
+            MouseAction::WheelDown
+        };
+        for _ in 0..lines.abs() {
+            self.report_mouse(action);
+        }
+    }
+    pub fn cursor_moved(&mut self, position: PhysicalPosition<f64>) {
+        self.mouse_position = position;
+        let cell = self.cell.cell_at(position, self.size);
+        // SGR-pixels reports every pixel moved, the others only new cells
+        let pixels = self.term.mouse_modes().encoding == MouseEncoding::SgrPixels;
+        if cell == self.mouse_cell && !pixels {
+            return;
+        }
+        self.mouse_cell = cell;
+        if self.selecting {
+            let point = self.mouse_point();
+            if let Some(selection) = &mut self.selection {
+                selection.update(point);
+            }
+        } else {
+            self.report_mouse(MouseAction::Motion(self.mouse_held));
+        }
+    }
+    pub fn mouse_input(&mut self, state: ElementState, button: MouseButton) {
+        let action = match state {
+            ElementState::Pressed => {
+                self.mouse_held = Some(button);
+                MouseAction::Press(button)
+            }
+            ElementState::Released => {
+                if self.mouse_held == Some(button) {
+                    self.mouse_held = None;
+                }
+                MouseAction::Release(button)
+            }
+        };
+        if button == MouseButton::Middle && !self.mouse_reporting() {
+            if state == ElementState::Pressed {
+                self.paste(ClipboardKind::Primary);
+            }
+            return;
+        }
+        if button == MouseButton::Left && (self.selecting || !self.mouse_reporting()) {
+            match state {
+                ElementState::Pressed => self.start_selection(),
+                ElementState::Released => self.finish_selection(),
+            }
+            return;
+        }
+        self.report_mouse(action);
+    }
+    // Shift keeps the mouse for selecting while the program wants reports
+    fn mouse_reporting(&self) -> bool {
+        self.term.mouse_modes().mode != MouseMode::Off && !self.modifiers.shift()
+    }
+    fn mouse_point(&self) -> Point {
+        let (col, line) = self.mouse_cell;
+        Point {
+            line: self.term.visible_line(line),
+            col,
+        }
+    }
+    fn start_selection(&mut self) {
+        let point = self.mouse_point();
+        let now = Instant::now();
+        self.clicks = match self.last_click {
+            Some((time, last)) if last == point && now - time < MULTI_CLICK_INTERVAL => {
+                self.clicks % 3 + 1
+            }
+            _ => 1,
+        };
+        self.last_click = Some((now, point));
+        let kind = match self.clicks {
+            2 => SelectionKind::Word,
+            3 => SelectionKind::Line,
+            _ if self.modifiers.alt() => SelectionKind::Block,
+            _ => SelectionKind::Simple,
+        };
+        self.selection = Some(Selection::new(kind, point));
+        self.selecting = true;
+    }
+    fn finish_selection(&mut self) {
+        self.selecting = false;
+        let text = self.selection_text();
+        if !text.is_empty() {
+            self.clipboard.store(ClipboardKind::Primary, text);
+        }
+    }
+    fn selection_text(&self) -> String {
+        match &self.selection {
+            Some(selection) => selection.text(&self.term, &self.config.word_separators),
+            None => String::new(),
+        }
+    }
+    fn paste(&mut self, kind: ClipboardKind) {
+        let text = self.clipboard.load(kind);
+        let lines = text.trim_end().lines().count();
+        if self.config.confirm_paste && lines > 1 && !self.term.bracketed_paste() {
+            self.pending_paste = Some(text);
+        } else {
+            self.send_paste(&text);
+        }
+    }
+    fn send_paste(&mut self, text: &str) {
+        if !text.is_empty() {
+            let text = paste::encode(text, self.term.bracketed_paste());
+            self.send(text.as_bytes());
+        }
+    }
+    fn confirm_paste(&mut self, confirmed: bool) {
+        self.keyboard.suppress_character();
+        if let Some(text) = self.pending_paste.take() {
+            if confirmed {
+                self.send_paste(&text);
+            }
+        }
+    }
+    // Ctrl+Shift, or Cmd on macOS, for the clipboard shortcuts
+    fn clipboard_shortcut(&self) -> bool {
+        if cfg!(target_os = "macos") {
+            self.modifiers == ModifiersState::LOGO
+        } else {
+            self.modifiers == ModifiersState::CTRL | ModifiersState::SHIFT
+        }
+    }
+    fn report_mouse(&mut self, action: MouseAction) {
+        let (col, line) = self.mouse_cell;
+        let report = MouseReport {
+            action,
+            col,
+            line,
+            x: self.mouse_position.x.max(0.0) as u32,
+            y: self.mouse_position.y.max(0.0) as u32,
+            modifiers: self.modifiers,
+        };
+        if let Some(bytes) = mouse::encode(&report, self.term.mouse_modes()) {
+            self.write(&bytes);
+        }
+    }
+    // non-text keys, typed text arrives through received_character
+    pub fn update(&mut self, key: Option<VirtualKeyCode>, state: ElementState) {
+        self.keyboard.key_event(state);
+        let key = match key {
+            Some(key) => key,
+            None => return,
+        };
+        if state == ElementState::Pressed && self.pending_paste.is_some() {
+            // the answer is typed, see received_character
+            match key {
+                VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => self.confirm_paste(true),
+                VirtualKeyCode::Escape => self.confirm_paste(false),
+                _ => (),
+            }
+            return;
+        }
+        if state == ElementState::Pressed && self.clipboard_shortcut() {
+            match key {
+                VirtualKeyCode::C => {
+                    let text = self.selection_text();
+                    // nothing selected leaves the clipboard alone
+                    if !text.is_empty() {
+                        self.clipboard.store(ClipboardKind::Clipboard, text);
+                    }
+                    self.keyboard.suppress_character();
+                    return;
+                }
+                VirtualKeyCode::V => {
+                    self.paste(ClipboardKind::Clipboard);
+                    self.keyboard.suppress_character();
+                    return;
+                }
+                _ => (),
+            }
+        }
+        if state == ElementState::Pressed
+            && key == VirtualKeyCode::Insert
+            && self.modifiers == ModifiersState::SHIFT
+        {
+            return self.paste(ClipboardKind::Primary);
+        }
+        // on the alternate screen Shift+PageUp goes to the program
+        let scrollback = !self.term.alt_screen();
+        if self.modifiers.shift() && state == ElementState::Pressed && scrollback {
+            let page = self.size.rows as isize;
+            match key {
+                VirtualKeyCode::PageUp => return self.term.scroll_display(page),
+                VirtualKeyCode::PageDown => return self.term.scroll_display(-page),
+                _ => (),
+            }
+        }
+        let modifiers = self.key_modifiers();
+        let modes = self.term.key_modes();
+        if let Some(sequence) = self.keyboard.key(key, state, modifiers, modes) {
+            self.send(sequence.as_bytes());
+        }
+    }
+    pub fn received_character(&mut self, c: char) {
+        // macOS reports function keys as characters in the private use area
+        if self.keyboard.take_suppressed() || ('\u{f700}'..='\u{f8ff}').contains(&c) {
+            return;
+        }
+        if self.pending_paste.is_some() {
+            return self.confirm_paste(c == 'y' || c == 'Y');
+        }
+        let text = keyboard::encode_char(c, self.key_modifiers());
+        self.send(text.as_bytes());
+    }
+    // Alt is left to the platform for composing characters unless it is
+    // configured to act as meta
+    fn key_modifiers(&self) -> ModifiersState {
+        let mut modifiers = self.modifiers;
+        if !self.config.option_as_meta {
+            modifiers.remove(ModifiersState::ALT);
+        }
+        modifiers
+    }
+    // input for the child process, the view jumps back to the bottom
+    fn send(&mut self, bytes: &[u8]) {
+        self.term.scroll_to_bottom();
+        self.write(bytes);
+    }
+    fn write(&mut self, bytes: &[u8]) {
+        // a held process that exited has nobody left to read its input
+        if self.exited {
+            return;
+        }
+        if let Some(process) = &mut self.process {
+            process.write(bytes);
+        }
     }
 }
 impl crate::Updater for TextRender {
     fn update(&mut self, image: &mut image::RgbaImage) {
-        let read_string = self.process.read();
-        for c in read_string.chars() {
-            self.buffer.push(c);
-        }
-        let (x, y) = image.dimensions();
-        if x != self.canvas.size.x() as u32 || y != self.canvas.size.y() as u32 {
-            println!(
-                "canvas size: x: {} y: {}",
-                self.canvas.size.x(),
-                self.canvas.size.y()
+        self.read_process();
+        self.check_exit();
+        let blink_on =
+            (self.started.elapsed().as_millis() / BLINK_INTERVAL.as_millis()).is_multiple_of(2);
+        self.blinking = false;
+        let cursor = self.term.cursor();
+        let offset = self.term.display_offset();
+        let (cursor_line, cursor_col) = (cursor.line + offset, cursor.col);
+        let selection = self
+            .selection
+            .as_ref()
+            .and_then(|selection| selection.range(&self.term, &self.config.word_separators));
+        for line in 0..self.term.grid().lines() {
+            let row = self.term.display_row(line);
+            let visible_line = self.term.visible_line(line);
+            for col in 0..self.term.grid().cols() {
+                // rows from the scrollback are trimmed or were made at another width
+                let mut cell = row.cells().get(col).copied().unwrap_or_default();
+                if (line, col) == (cursor_line, cursor_col) {
+                    cell.flags.toggle(Flags::INVERSE);
+                }
+                let point = Point {
+                    line: visible_line,
+                    col,
+                };
+                if selection.is_some_and(|selection| selection.contains(point)) {
+                    cell.flags.toggle(Flags::INVERSE);
+                }
+                if cell.flags.contains(Flags::BLINK) {
+                    self.blinking = true;
+                    if !blink_on {
+                        cell.flags.insert(Flags::HIDDEN);
+                    }
+                }
+                let x = (self.cell.width * col as f32) as u32;
+                let y = (self.cell.height * line as f32) as u32;
+                draw_cell(image, &mut self.glyphs, &self.cell, x, y, &cell);
+            }
+        }
+        if let Some(text) = &self.pending_paste {
+            // the question covers the bottom line until it is answered
+            let prompt = format!("Paste {} lines? (y/n)", text.trim_end().lines().count());
+            let y = (self.cell.height * (self.term.grid().lines() - 1) as f32) as u32;
+            let mut prompt = prompt.chars();
+            for col in 0..self.term.grid().cols() {
+                let cell = Cell {
+                    c: prompt.next().unwrap_or(' '),
+                    flags: Flags::INVERSE,
+                    ..Cell::default()
+                };
+                let x = (self.cell.width * col as f32) as u32;
+                draw_cell(image, &mut self.glyphs, &self.cell, x, y, &cell);
+            }
+        }
+    }
+}
+fn draw_cell(
+    image: &mut image::RgbaImage,
+    glyphs: &mut GlyphCache,
+    metrics: &CellMetrics,
+    x: u32,
+    y: u32,
+    cell: &Cell,
+) {
+    let (fg, bg) = cell_colors(cell);
+    let underline = color::to_rgb(cell.underline_color, fg);
+    let glyph = if cell.c == ' ' || cell.flags.contains(Flags::HIDDEN) {
+        None
+    } else {
+        glyphs.get(
+            cell.c,
+            cell.flags.contains(Flags::BOLD),
+            cell.flags.contains(Flags::ITALIC),
+        )
+    };
+    let width = metrics.width as u32;
+    let height = metrics.height as u32;
+    let underline_y = (metrics.baseline as u32 + 1).min(height - 1);
+    let strikeout_y = metrics.baseline as u32 * 2 / 3;
+    for dy in 0..height.min(image.height().saturating_sub(y)) {
+        for dx in 0..width.min(image.width().saturating_sub(x)) {
+            let coverage = glyph
+                .map(|glyph| glyph.pixels[dy as usize * glyph.stride + dx as usize])
+                .unwrap_or(0);
+            let mut pixel = blend(bg, fg, coverage);
+            let flags = cell.flags;
+            if (flags.contains(Flags::OVERLINE) && dy == 0)
+                || (flags.contains(Flags::STRIKEOUT) && dy == strikeout_y)
+            {
+                pixel = fg;
+            }
+            if underline_at(flags, dx, dy, underline_y) {
+                pixel = underline;
+            }
+            image.put_pixel(
+                x + dx,
+                y + dy,
+                image::Rgba([pixel[0], pixel[1], pixel[2], 255]),
             );
-            println!("x: {} y: {}", x, y);
-            self.canvas = Canvas::new(Vector2I::new(x as i32, y as i32), Format::Rgb24);
-        }
-        let mut i = 0;
-        for c in self.buffer.iter() {
-            let glyph_id = self.font.glyph_for_char(c.clone()).unwrap();
-            self.font
-                .rasterize_glyph(
-                    &mut self.canvas,
-                    glyph_id,
-                    12.0,
-                    Transform2F::from_translation(Vector2F::new(12.0 * (i as f32), 32.0)),
-                    HintingOptions::None,
-                    RasterizationOptions::Bilevel,
-                )
-                .unwrap();
-            i += 1;
         }
-
-        *image = image::RgbImage::from_raw(x, y, self.canvas.pixels.clone())
-            .unwrap()
-            .convert();
     }
 }
+fn underline_at(flags: Flags, dx: u32, dy: u32, y: u32) -> bool {
+    if flags.contains(Flags::UNDERLINE) {
+        dy == y
+    } else if flags.contains(Flags::DOUBLE_UNDERLINE) {
+        // the second line goes above the first if there is no room below it
+        dy == y || dy == if y >= 2 { y - 2 } else { y + 2 }
+    } else if flags.contains(Flags::CURLY_UNDERLINE) {
+        dy + [1, 0, 0, 1, 2, 2][dx as usize % 6] == y + 1
+    } else if flags.contains(Flags::DOTTED_UNDERLINE) {
+        dy == y && dx.is_multiple_of(2)
+    } else if flags.contains(Flags::DASHED_UNDERLINE) {
+        dy == y && dx % 6 < 4
+    } else {
+        false
+    }
+}
+fn cell_colors(cell: &Cell) -> ([u8; 3], [u8; 3]) {
+    let fg = match cell.fg {
+        // bold text uses the bright version of the first eight colours
+        Color::Indexed(index) if index < 8 && cell.flags.contains(Flags::BOLD) => {
+            color::indexed(index + 8)
+        }
+        fg => color::to_rgb(fg, color::DEFAULT_FOREGROUND),
+    };
+    let mut fg = if cell.flags.contains(Flags::DIM) {
+        [fg[0] / 3 * 2, fg[1] / 3 * 2, fg[2] / 3 * 2]
+    } else {
+        fg
+    };
+    let mut bg = color::to_rgb(cell.bg, color::DEFAULT_BACKGROUND);
+    if cell.flags.contains(Flags::INVERSE) {
+        std::mem::swap(&mut fg, &mut bg);
+    }
+    (fg, bg)
+}
+fn blend(bg: [u8; 3], fg: [u8; 3], coverage: u8) -> [u8; 3] {
+    let mix = |bg: u8, fg: u8| {
+        ((bg as u32 * (255 - coverage as u32) + fg as u32 * coverage as u32) / 255) as u8
+    };
+    [mix(bg[0], fg[0]), mix(bg[1], fg[1]), mix(bg[2], fg[2])]
+}
@@ -0,0 +1,64 @@
+use copypasta::{ClipboardContext, ClipboardProvider};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClipboardKind {
+    Clipboard,
+    // the X11 primary selection, the clipboard is used where there is none
+    Primary,
+}
+// The system clipboard, failures are printed and otherwise ignored.
+pub struct Clipboard {
+    clipboard: Option<Box<dyn ClipboardProvider>>,
+    primary: Option<Box<dyn ClipboardProvider>>,
+}
+impl Clipboard {
+    pub fn new() -> Clipboard {
+        Clipboard {
+            clipboard: provider(ClipboardContext::new()),
+            primary: primary(),
+        }
+    }
+    pub fn store(&mut self, kind: ClipboardKind, text: String) {
+        if let Some(provider) = self.provider(kind) {
+            if let Err(e) = provider.set_contents(text) {
+                eprintln!("failed to set clipboard: {}", e);
+            }
+        }
+    }
+    pub fn load(&mut self, kind: ClipboardKind) -> String {
+        match self.provider(kind).map(|provider| provider.get_contents()) {
+            Some(Ok(text)) => text,
+            Some(Err(e)) => {
+                eprintln!("failed to read clipboard: {}", e);
+                String::new()
+            }
+            None => String::new(),
+        }
+    }
+    fn provider(&mut self, kind: ClipboardKind) -> Option<&mut Box<dyn ClipboardProvider>> {
+        match kind {
+            ClipboardKind::Primary if self.primary.is_some() => self.primary.as_mut(),
+            _ => self.clipboard.as_mut(),
+        }
+    }
+}
+fn provider<P: ClipboardProvider + 'static, E: std::fmt::Display>(
+    context: Result<P, E>,
+) -> Option<Box<dyn ClipboardProvider>> {
+    match context {
+        Ok(context) => Some(Box::new(context)),
+        Err(e) => {
+            eprintln!("failed to open clipboard: {}", e);
+            None
+        }
+    }
+}
+#[cfg(all(unix, not(target_os = "macos")))]
+fn primary() -> Option<Box<dyn ClipboardProvider>> {
+    use copypasta::x11_clipboard::{Primary, X11ClipboardContext};
+    provider(X11ClipboardContext::<Primary>::new())
+}
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn primary() -> Option<Box<dyn ClipboardProvider>> {
+    None
+}
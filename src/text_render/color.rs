@@ -0,0 +1,47 @@
+use super::grid::Color;
+
+pub const DEFAULT_FOREGROUND: [u8; 3] = [229, 229, 229];
+pub const DEFAULT_BACKGROUND: [u8; 3] = [0, 0, 0];
+
+// xterm's default values for the 16 ANSI colours
+const ANSI: [[u8; 3]; 16] = [
+    [0, 0, 0],
+    [205, 0, 0],
+    [0, 205, 0],
+    [205, 205, 0],
+    [0, 0, 238],
+    [205, 0, 205],
+    [0, 205, 205],
+    [229, 229, 229],
+    [127, 127, 127],
+    [255, 0, 0],
+    [0, 255, 0],
+    [255, 255, 0],
+    [92, 92, 255],
+    [255, 0, 255],
+    [0, 255, 255],
+    [255, 255, 255],
+];
+
+// the 256 colour palette: ANSI colours, a 6x6x6 colour cube and a grey ramp
+pub fn indexed(index: u8) -> [u8; 3] {
+    match index {
+        0..=15 => ANSI[index as usize],
+        16..=231 => {
+            let index = index - 16;
+            let level = |value: u8| if value == 0 { 0 } else { value * 40 + 55 };
+            [level(index / 36), level(index / 6 % 6), level(index % 6)]
+        }
+        _ => {
+            let grey = (index - 232) * 10 + 8;
+            [grey, grey, grey]
+        }
+    }
+}
+pub fn to_rgb(color: Color, default: [u8; 3]) -> [u8; 3] {
+    match color {
+        Color::Default => default,
+        Color::Indexed(index) => indexed(index),
+        Color::Rgb(r, g, b) => [r, g, b],
+    }
+}
@@ -0,0 +1,77 @@
+use super::{CellMetrics, FONT_SIZE};
+use font_kit::canvas::{Canvas, Format, RasterizationOptions};
+use font_kit::family_name::FamilyName;
+use font_kit::font::Font;
+use font_kit::hinting::HintingOptions;
+use font_kit::properties::{Properties, Style, Weight};
+use font_kit::source::SystemSource;
+use pathfinder_geometry::transform2d::Transform2F;
+use pathfinder_geometry::vector::{Vector2F, Vector2I};
+use std::collections::HashMap;
+
+// Rasterizes glyphs once into cell sized coverage masks and keeps them for
+// the following frames.
+pub struct GlyphCache {
+    // regular, bold, italic and bold italic faces
+    fonts: Vec<Font>,
+    cell: CellMetrics,
+    glyphs: HashMap<(char, usize), Option<Canvas>>,
+}
+impl GlyphCache {
+    pub fn new(regular: Font, cell: CellMetrics) -> GlyphCache {
+        let styles = [
+            (Weight::BOLD, Style::Normal),
+            (Weight::NORMAL, Style::Italic),
+            (Weight::BOLD, Style::Italic),
+        ];
+        let mut fonts = vec![regular.clone()];
+        for (weight, style) in styles.iter() {
+            let properties = Properties {
+                weight: *weight,
+                style: *style,
+                ..Properties::new()
+            };
+            let font = SystemSource::new()
+                .select_best_match(&[FamilyName::Monospace], &properties)
+                .ok()
+                .and_then(|handle| handle.load().ok())
+                .unwrap_or_else(|| regular.clone());
+            fonts.push(font);
+        }
+        GlyphCache {
+            fonts,
+            cell,
+            glyphs: HashMap::new(),
+        }
+    }
+    pub fn get(&mut self, c: char, bold: bool, italic: bool) -> Option<&Canvas> {
+        let style = bold as usize + 2 * italic as usize;
+        let fonts = &self.fonts;
+        let cell = self.cell;
+        self.glyphs
+            .entry((c, style))
+            .or_insert_with(|| {
+                // fall back to the regular face for characters the styled one lacks
+                let font = &fonts[style];
+                let (font, glyph_id) = match font.glyph_for_char(c) {
+                    Some(glyph_id) => (font, glyph_id),
+                    None => (&fonts[0], fonts[0].glyph_for_char(c)?),
+                };
+                let mut canvas = Canvas::new(
+                    Vector2I::new(cell.width as i32, cell.height as i32),
+                    Format::A8,
+                );
+                font.rasterize_glyph(
+                    &mut canvas,
+                    glyph_id,
+                    FONT_SIZE,
+                    Transform2F::from_translation(Vector2F::new(0.0, cell.baseline)),
+                    HintingOptions::None,
+                    RasterizationOptions::GrayscaleAa,
+                )
+                .ok()?;
+                Some(canvas)
+            })
+            .as_ref()
+    }
+}
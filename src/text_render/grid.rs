@@ -0,0 +1,231 @@
+use bitflags::bitflags;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Color {
+    Default,
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+bitflags! {
+    pub struct Flags: u16 {
+        const BOLD = 1;
+        const DIM = 1 << 1;
+        const ITALIC = 1 << 2;
+        const UNDERLINE = 1 << 3;
+        const DOUBLE_UNDERLINE = 1 << 4;
+        const CURLY_UNDERLINE = 1 << 5;
+        const DOTTED_UNDERLINE = 1 << 6;
+        const DASHED_UNDERLINE = 1 << 7;
+        const BLINK = 1 << 8;
+        const INVERSE = 1 << 9;
+        const HIDDEN = 1 << 10;
+        const STRIKEOUT = 1 << 11;
+        const OVERLINE = 1 << 12;
+        const ALL_UNDERLINES = Self::UNDERLINE.bits
+            | Self::DOUBLE_UNDERLINE.bits
+            | Self::CURLY_UNDERLINE.bits
+            | Self::DOTTED_UNDERLINE.bits
+            | Self::DASHED_UNDERLINE.bits;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cell {
+    pub c: char,
+    pub fg: Color,
+    pub bg: Color,
+    // Color::Default draws underlines in the foreground colour
+    pub underline_color: Color,
+    pub flags: Flags,
+}
+impl Default for Cell {
+    fn default() -> Cell {
+        Cell {
+            c: ' ',
+            fg: Color::Default,
+            bg: Color::Default,
+            underline_color: Color::Default,
+            flags: Flags::empty(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Row {
+    cells: Vec<Cell>,
+    // the text continues on the next row because it was wrapped at the last
+    // column, rather than ending with a newline
+    wrapped: bool,
+}
+impl Row {
+    pub fn new(cols: usize, blank: Cell) -> Row {
+        Row {
+            cells: vec![blank; cols],
+            wrapped: false,
+        }
+    }
+    pub fn from_cells(cells: Vec<Cell>, wrapped: bool) -> Row {
+        Row { cells, wrapped }
+    }
+    pub fn cells(&self) -> &[Cell] {
+        &self.cells
+    }
+    pub fn into_cells(self) -> Vec<Cell> {
+        self.cells
+    }
+    pub fn wrapped(&self) -> bool {
+        self.wrapped
+    }
+    pub fn set_wrapped(&mut self, wrapped: bool) {
+        self.wrapped = wrapped;
+    }
+    pub fn resize(&mut self, cols: usize) {
+        self.cells.resize(cols, Cell::default());
+    }
+    pub fn erase(&mut self, start: usize, end: usize, blank: Cell) {
+        let end = end.min(self.cells.len());
+        for cell in self.cells[start.min(end)..end].iter_mut() {
+            *cell = blank;
+        }
+    }
+    // shifts the cells in col..right to the right, cells pushed past right are lost
+    pub fn insert_cells(&mut self, col: usize, right: usize, count: usize, blank: Cell) {
+        let count = count.min(right - col);
+        self.cells[col..right].rotate_right(count);
+        self.erase(col, col + count, blank);
+    }
+    // shifts the cells in col..right to the left over col, blanks fill in at right
+    pub fn delete_cells(&mut self, col: usize, right: usize, count: usize, blank: Cell) {
+        let count = count.min(right - col);
+        self.cells[col..right].rotate_left(count);
+        self.erase(right - count, right, blank);
+    }
+    // drops trailing cells equal to blank, the row gets shorter than the grid
+    pub fn trim(&mut self, blank: Cell) {
+        let len = self
+            .cells
+            .iter()
+            .rposition(|cell| *cell != blank)
+            .map_or(0, |last| last + 1);
+        self.cells.truncate(len);
+        self.cells.shrink_to_fit();
+    }
+    pub fn text(&self) -> String {
+        self.cells.iter().map(|cell| cell.c).collect()
+    }
+}
+
+// A rectangle of lines top..bottom and columns left..right.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Region {
+    pub top: usize,
+    pub bottom: usize,
+    pub left: usize,
+    pub right: usize,
+}
+
+// The visible screen, row 0 is the top line.
+pub struct Grid {
+    rows: Vec<Row>,
+    cols: usize,
+}
+impl Grid {
+    pub fn new(cols: usize, lines: usize) -> Grid {
+        Grid {
+            rows: vec![Row::new(cols, Cell::default()); lines],
+            cols,
+        }
+    }
+    // every row must be cols wide
+    pub fn from_rows(cols: usize, rows: Vec<Row>) -> Grid {
+        Grid { rows, cols }
+    }
+    pub fn into_rows(self) -> Vec<Row> {
+        self.rows
+    }
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+    pub fn lines(&self) -> usize {
+        self.rows.len()
+    }
+    pub fn rows(&self) -> &[Row] {
+        &self.rows
+    }
+    pub fn row_mut(&mut self, line: usize) -> &mut Row {
+        &mut self.rows[line]
+    }
+    pub fn cell_mut(&mut self, line: usize, col: usize) -> &mut Cell {
+        &mut self.rows[line].cells[col]
+    }
+    pub fn full_region(&self) -> Region {
+        Region {
+            top: 0,
+            bottom: self.lines(),
+            left: 0,
+            right: self.cols,
+        }
+    }
+    // moves the contents of the region up by count, blank cells fill the gap
+    // at the bottom. When the region spans whole rows the rows that scroll off
+    // are returned.
+    pub fn scroll_up(&mut self, region: Region, count: usize, blank: Cell) -> Vec<Row> {
+        let Region {
+            top,
+            bottom,
+            left,
+            right,
+        } = region;
+        let count = count.min(bottom - top);
+        if left == 0 && right == self.cols {
+            let removed = self.rows.drain(top..top + count).collect();
+            let at = bottom - count;
+            let cols = self.cols;
+            self.rows
+                .splice(at..at, (0..count).map(|_| Row::new(cols, blank)));
+            return removed;
+        }
+        for line in top..bottom {
+            if line + count < bottom {
+                let source = self.rows[line + count].cells[left..right].to_vec();
+                self.rows[line].cells[left..right].copy_from_slice(&source);
+            } else {
+                self.rows[line].erase(left, right, blank);
+            }
+        }
+        vec![]
+    }
+    // moves the contents of the region down by count, blank cells fill the gap at the top
+    pub fn scroll_down(&mut self, region: Region, count: usize, blank: Cell) {
+        let Region {
+            top,
+            bottom,
+            left,
+            right,
+        } = region;
+        let count = count.min(bottom - top);
+        if left == 0 && right == self.cols {
+            self.rows.drain(bottom - count..bottom);
+            let cols = self.cols;
+            self.rows
+                .splice(top..top, (0..count).map(|_| Row::new(cols, blank)));
+            return;
+        }
+        for line in (top..bottom).rev() {
+            if line >= top + count {
+                let source = self.rows[line - count].cells[left..right].to_vec();
+                self.rows[line].cells[left..right].copy_from_slice(&source);
+            } else {
+                self.rows[line].erase(left, right, blank);
+            }
+        }
+    }
+    pub fn resize(&mut self, cols: usize, lines: usize) {
+        for row in self.rows.iter_mut() {
+            row.resize(cols);
+        }
+        self.rows.resize(lines, Row::new(cols, Cell::default()));
+        self.cols = cols;
+    }
+}
@@ -0,0 +1,677 @@
+use bitflags::bitflags;
+use std::collections::HashSet;
+use winit::event::{ElementState, ModifiersState, VirtualKeyCode};
+
+bitflags! {
+    // progressive enhancements of the kitty keyboard protocol
+    // (https://sw.kovidgoyal.net/kitty/keyboard-protocol/), associated text
+    // is not supported
+    #[derive(Default)]
+    pub struct KittyFlags: u8 {
+        const DISAMBIGUATE = 1;
+        const EVENT_TYPES = 1 << 1;
+        const ALTERNATE_KEYS = 1 << 2;
+        const ALL_KEYS = 1 << 3;
+    }
+}
+
+// Input modes the program in the terminal can set.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct KeyModes {
+    // DECCKM, cursor keys send SS3 instead of CSI
+    pub app_cursor: bool,
+    // DECKPAM, the keypad sends SS3 sequences instead of its characters
+    pub app_keypad: bool,
+    // xterm's modifyOtherKeys mode 2
+    pub modify_other_keys: bool,
+    // empty while the kitty protocol is off
+    pub kitty_flags: KittyFlags,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyAction {
+    Press,
+    // the OS repeats a held key by sending more presses without releases
+    Repeat,
+    Release,
+}
+// Tracks which keys are held down to tell auto-repeat from new presses.
+#[derive(Default)]
+pub struct Keyboard {
+    held: HashSet<VirtualKeyCode>,
+    // the character of a key already sent as a sequence is dropped
+    suppress_character: bool,
+}
+impl Keyboard {
+    // called for every key event, with or without a key code, as any new
+    // press lets characters through again
+    pub fn key_event(&mut self, state: ElementState) {
+        if state == ElementState::Pressed {
+            self.suppress_character = false;
+        }
+    }
+    pub fn suppress_character(&mut self) {
+        self.suppress_character = true;
+    }
+    // whether the character following the last key press is dropped
+    pub fn take_suppressed(&mut self) -> bool {
+        std::mem::take(&mut self.suppress_character)
+    }
+    pub fn input(&mut self, key: VirtualKeyCode, state: ElementState) -> KeyAction {
+        match state {
+            ElementState::Pressed if self.held.insert(key) => KeyAction::Press,
+            ElementState::Pressed => KeyAction::Repeat,
+            ElementState::Released => {
+                self.held.remove(&key);
+                KeyAction::Release
+            }
+        }
+    }
+    // releases are not delivered while the window is unfocused
+    pub fn clear(&mut self) {
+        self.held.clear();
+    }
+    // the bytes for a key event, releases only send something when the kitty
+    // protocol asks for them
+    pub fn key(
+        &mut self,
+        key: VirtualKeyCode,
+        state: ElementState,
+        modifiers: ModifiersState,
+        modes: KeyModes,
+    ) -> Option<String> {
+        let action = self.input(key, state);
+        let sequence = sequence(key, action, modifiers, modes);
+        if sequence.is_some() {
+            // keys like Escape, Enter and the keypad also arrive as a character right after
+            self.suppress_character = state == ElementState::Pressed;
+        }
+        sequence
+    }
+}
+// the kitty protocol when it is on, then modifyOtherKeys, then what xterm sends
+fn sequence(
+    key: VirtualKeyCode,
+    action: KeyAction,
+    modifiers: ModifiersState,
+    modes: KeyModes,
+) -> Option<String> {
+    if !modes.kitty_flags.is_empty() {
+        return kitty(key, action, modifiers, modes.kitty_flags);
+    }
+    if action == KeyAction::Release {
+        return None;
+    }
+    if modes.modify_other_keys {
+        if let Some(sequence) = modify_other_keys(key, modifiers) {
+            return Some(sequence);
+        }
+    }
+    encode(key, modifiers, modes)
+}
+
+// how a key without a character is sent, following xterm
+enum Encoding {
+    // CSI code ~
+    Tilde(u8),
+    // SS3 final, or CSI 1;m final with modifiers
+    Ss3(u8),
+    // a cursor key, SS3 in application cursor mode
+    Cursor(u8),
+}
+
+// The bytes xterm sends for a key that does not produce text, None for keys
+// handled as typed text or not at all.
+pub fn encode(key: VirtualKeyCode, modifiers: ModifiersState, modes: KeyModes) -> Option<String> {
+    use VirtualKeyCode::*;
+    if modes.app_keypad {
+        if let Some(final_byte) = keypad(key) {
+            return Some(format!("\x1bO{}", final_byte as char));
+        }
+    }
+    let encoding = match key {
+        Escape => return Some(meta(modifiers, '\x1b')),
+        // Ctrl+Backspace is the other erase character
+        Back if modifiers.ctrl() => return Some(meta(modifiers, '\x08')),
+        Back => return Some(meta(modifiers, '\x7f')),
+        Return | NumpadEnter => return Some(meta(modifiers, '\r')),
+        Tab if modifiers.shift() => return Some("\x1b[Z".to_string()),
+        Tab => return Some(meta(modifiers, '\t')),
+        Up => Encoding::Cursor(b'A'),
+        Down => Encoding::Cursor(b'B'),
+        Right => Encoding::Cursor(b'C'),
+        Left => Encoding::Cursor(b'D'),
+        Home => Encoding::Cursor(b'H'),
+        End => Encoding::Cursor(b'F'),
+        Insert => Encoding::Tilde(2),
+        Delete => Encoding::Tilde(3),
+        PageUp => Encoding::Tilde(5),
+        PageDown => Encoding::Tilde(6),
+        F1 => Encoding::Ss3(b'P'),
+        F2 => Encoding::Ss3(b'Q'),
+        F3 => Encoding::Ss3(b'R'),
+        F4 => Encoding::Ss3(b'S'),
+        F5 => Encoding::Tilde(15),
+        F6 => Encoding::Tilde(17),
+        F7 => Encoding::Tilde(18),
+        F8 => Encoding::Tilde(19),
+        F9 => Encoding::Tilde(20),
+        F10 => Encoding::Tilde(21),
+        F11 => Encoding::Tilde(23),
+        F12 => Encoding::Tilde(24),
+        _ => return None,
+    };
+    let m = modifier_param(modifiers);
+    Some(match encoding {
+        Encoding::Tilde(code) if m > 1 => format!("\x1b[{};{}~", code, m),
+        Encoding::Tilde(code) => format!("\x1b[{}~", code),
+        Encoding::Ss3(final_byte) | Encoding::Cursor(final_byte) if m > 1 => {
+            format!("\x1b[1;{}{}", m, final_byte as char)
+        }
+        Encoding::Cursor(final_byte) if !modes.app_cursor => {
+            format!("\x1b[{}", final_byte as char)
+        }
+        Encoding::Ss3(final_byte) | Encoding::Cursor(final_byte) => {
+            format!("\x1bO{}", final_byte as char)
+        }
+    })
+}
+// In modifyOtherKeys mode 2 keys that would lose a modifier as a plain or
+// control character are sent as CSI 27;m;code~ instead.
+fn modify_other_keys(key: VirtualKeyCode, modifiers: ModifiersState) -> Option<String> {
+    use VirtualKeyCode::*;
+    if !(modifiers.ctrl() || modifiers.alt() || modifiers.logo()) {
+        return None;
+    }
+    let code = match key {
+        Return => 13,
+        Tab => 9,
+        Back => 127,
+        Escape => 27,
+        _ if modifiers.shift() => shifted(text_key(key)?) as u32,
+        _ => text_key(key)? as u32,
+    };
+    Some(format!("\x1b[27;{};{}~", modifier_param(modifiers), code))
+}
+
+// how the kitty protocol sends a key
+enum KittyKey {
+    // a key that types a character, sent as text unless modified
+    Text(char),
+    // Enter, Tab and Backspace keep their legacy bytes unless modified
+    Legacy(u32),
+    // CSI code u
+    Code(u32),
+    // modifier and lock keys, only reported when all keys are
+    Modifier(u32),
+    // CSI code ~
+    Tilde(u32),
+    // CSI 1 final, the 1 is left out without modifiers
+    Letter(u8),
+}
+fn kitty(
+    key: VirtualKeyCode,
+    action: KeyAction,
+    modifiers: ModifiersState,
+    flags: KittyFlags,
+) -> Option<String> {
+    let event_types = flags.contains(KittyFlags::EVENT_TYPES);
+    if action == KeyAction::Release && !event_types {
+        return None;
+    }
+    let all_keys = flags.contains(KittyFlags::ALL_KEYS);
+    // shift alone still types text
+    let modified = !(modifiers - ModifiersState::SHIFT).is_empty();
+    let (number, final_byte) = match kitty_key(key)? {
+        KittyKey::Text(c) => {
+            if !all_keys && !modified {
+                return None;
+            }
+            let mut number = (c as u32).to_string();
+            if flags.contains(KittyFlags::ALTERNATE_KEYS) && modifiers.shift() {
+                number = format!("{}:{}", number, shifted(c) as u32);
+            }
+            (number, 'u')
+        }
+        KittyKey::Legacy(_) if !all_keys && modifiers.is_empty() => {
+            return match action {
+                KeyAction::Release => None,
+                _ => encode(key, modifiers, KeyModes::default()),
+            };
+        }
+        KittyKey::Modifier(_) if !all_keys => return None,
+        KittyKey::Legacy(code) | KittyKey::Code(code) | KittyKey::Modifier(code) => {
+            (code.to_string(), 'u')
+        }
+        KittyKey::Tilde(code) => (code.to_string(), '~'),
+        KittyKey::Letter(final_byte) => ("1".to_string(), final_byte as char),
+    };
+    let event = match action {
+        KeyAction::Press => 1,
+        KeyAction::Repeat => 2,
+        KeyAction::Release => 3,
+    };
+    let m = modifier_param(modifiers);
+    let params = if event_types && event > 1 {
+        format!(";{}:{}", m, event)
+    } else if m > 1 {
+        format!(";{}", m)
+    } else {
+        String::new()
+    };
+    if final_byte != 'u' && final_byte != '~' && params.is_empty() {
+        return Some(format!("\x1b[{}", final_byte));
+    }
+    Some(format!("\x1b[{}{}{}", number, params, final_byte))
+}
+fn kitty_key(key: VirtualKeyCode) -> Option<KittyKey> {
+    use KittyKey::*;
+    use VirtualKeyCode::*;
+    Some(match key {
+        Escape => Code(27),
+        Return => Legacy(13),
+        Tab => Legacy(9),
+        Back => Legacy(127),
+        Insert => Tilde(2),
+        Delete => Tilde(3),
+        PageUp => Tilde(5),
+        PageDown => Tilde(6),
+        Up => Letter(b'A'),
+        Down => Letter(b'B'),
+        Right => Letter(b'C'),
+        Left => Letter(b'D'),
+        Home => Letter(b'H'),
+        End => Letter(b'F'),
+        F1 => Letter(b'P'),
+        F2 => Letter(b'Q'),
+        // CSI R would be mistaken for a cursor position report
+        F3 => Tilde(13),
+        F4 => Letter(b'S'),
+        F5 => Tilde(15),
+        F6 => Tilde(17),
+        F7 => Tilde(18),
+        F8 => Tilde(19),
+        F9 => Tilde(20),
+        F10 => Tilde(21),
+        F11 => Tilde(23),
+        F12 => Tilde(24),
+        F13 => Code(57376),
+        F14 => Code(57377),
+        F15 => Code(57378),
+        F16 => Code(57379),
+        F17 => Code(57380),
+        F18 => Code(57381),
+        F19 => Code(57382),
+        F20 => Code(57383),
+        F21 => Code(57384),
+        F22 => Code(57385),
+        F23 => Code(57386),
+        F24 => Code(57387),
+        Snapshot => Code(57361),
+        Pause => Code(57362),
+        Apps => Code(57363),
+        Numpad0 => Code(57399),
+        Numpad1 => Code(57400),
+        Numpad2 => Code(57401),
+        Numpad3 => Code(57402),
+        Numpad4 => Code(57403),
+        Numpad5 => Code(57404),
+        Numpad6 => Code(57405),
+        Numpad7 => Code(57406),
+        Numpad8 => Code(57407),
+        Numpad9 => Code(57408),
+        Decimal => Code(57409),
+        Divide => Code(57410),
+        Multiply => Code(57411),
+        Subtract => Code(57412),
+        Add => Code(57413),
+        NumpadEnter => Code(57414),
+        NumpadEquals => Code(57415),
+        NumpadComma => Code(57416),
+        Capital => Modifier(57358),
+        Scroll => Modifier(57359),
+        Numlock => Modifier(57360),
+        LShift => Modifier(57441),
+        LControl => Modifier(57442),
+        LAlt => Modifier(57443),
+        LWin => Modifier(57444),
+        RShift => Modifier(57447),
+        RControl => Modifier(57448),
+        RAlt => Modifier(57449),
+        RWin => Modifier(57450),
+        _ => Text(text_key(key)?),
+    })
+}
+// The character of a key on a US layout. Winit only reports the character a
+// key typed after modifiers and layout are applied, not the key's own one.
+fn text_key(key: VirtualKeyCode) -> Option<char> {
+    use VirtualKeyCode::*;
+    let keys = [
+        (A, 'a'),
+        (B, 'b'),
+        (C, 'c'),
+        (D, 'd'),
+        (E, 'e'),
+        (F, 'f'),
+        (G, 'g'),
+        (H, 'h'),
+        (I, 'i'),
+        (J, 'j'),
+        (K, 'k'),
+        (L, 'l'),
+        (M, 'm'),
+        (N, 'n'),
+        (O, 'o'),
+        (P, 'p'),
+        (Q, 'q'),
+        (R, 'r'),
+        (S, 's'),
+        (T, 't'),
+        (U, 'u'),
+        (V, 'v'),
+        (W, 'w'),
+        (X, 'x'),
+        (Y, 'y'),
+        (Z, 'z'),
+        (Key0, '0'),
+        (Key1, '1'),
+        (Key2, '2'),
+        (Key3, '3'),
+        (Key4, '4'),
+        (Key5, '5'),
+        (Key6, '6'),
+        (Key7, '7'),
+        (Key8, '8'),
+        (Key9, '9'),
+        (Space, ' '),
+        (Minus, '-'),
+        (Equals, '='),
+        (LBracket, '['),
+        (RBracket, ']'),
+        (Backslash, '\\'),
+        (Semicolon, ';'),
+        (Apostrophe, '\''),
+        (Grave, '`'),
+        (Comma, ','),
+        (Period, '.'),
+        (Slash, '/'),
+    ];
+    keys.iter().find(|(k, _)| *k == key).map(|(_, c)| *c)
+}
+// the character shift gives on a US layout
+fn shifted(c: char) -> char {
+    let pairs = "1!2@3#4$5%6^7&8*9(0)-_=+[{]}\\|;:'\"`~,<.>/?";
+    let chars: Vec<char> = pairs.chars().collect();
+    match chars.chunks(2).find(|pair| pair[0] == c) {
+        Some(pair) => pair[1],
+        None => c.to_ascii_uppercase(),
+    }
+}
+// Typed text, Ctrl turns characters into C0 controls and Alt sends an ESC
+// first. Characters the platform already made into controls pass through.
+pub fn encode_char(c: char, modifiers: ModifiersState) -> String {
+    let c = if modifiers.ctrl() {
+        control(c).unwrap_or(c)
+    } else {
+        c
+    };
+    meta(modifiers, c)
+}
+fn meta(modifiers: ModifiersState, c: char) -> String {
+    if modifiers.alt() {
+        format!("\x1b{}", c)
+    } else {
+        c.to_string()
+    }
+}
+// the control character xterm sends for Ctrl and a character
+fn control(c: char) -> Option<char> {
+    Some(match c {
+        'a'..='z' | 'A'..='Z' => (c as u8 & 0x1f) as char,
+        '@' | ' ' | '2' => '\0',
+        '[' | '3' => '\x1b',
+        '\\' | '4' => '\x1c',
+        ']' | '5' => '\x1d',
+        '^' | '~' | '6' => '\x1e',
+        '_' | '-' | '/' | '7' => '\x1f',
+        '?' | '8' => '\x7f',
+        _ => return None,
+    })
+}
+// 1 plus a bit for each held modifier, 1 means none
+fn modifier_param(modifiers: ModifiersState) -> u8 {
+    1 + modifiers.shift() as u8
+        + 2 * modifiers.alt() as u8
+        + 4 * modifiers.ctrl() as u8
+        + 8 * modifiers.logo() as u8
+}
+// final bytes of the keypad keys in application keypad mode
+fn keypad(key: VirtualKeyCode) -> Option<u8> {
+    use VirtualKeyCode::*;
+    Some(match key {
+        Numpad0 => b'p',
+        Numpad1 => b'q',
+        Numpad2 => b'r',
+        Numpad3 => b's',
+        Numpad4 => b't',
+        Numpad5 => b'u',
+        Numpad6 => b'v',
+        Numpad7 => b'w',
+        Numpad8 => b'x',
+        Numpad9 => b'y',
+        Multiply => b'j',
+        Add => b'k',
+        NumpadComma => b'l',
+        Subtract => b'm',
+        Decimal => b'n',
+        Divide => b'o',
+        NumpadEnter => b'M',
+        NumpadEquals => b'X',
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::event::ElementState::{Pressed, Released};
+    use winit::event::VirtualKeyCode::*;
+
+    fn none() -> ModifiersState {
+        ModifiersState::empty()
+    }
+    #[test]
+    fn presses_and_repeats_send_releases_do_not() {
+        let mut keyboard = Keyboard::default();
+        let modes = KeyModes::default();
+        let events = [(Up, Pressed), (Up, Pressed), (Up, Released), (Up, Released)];
+        let sent: Vec<_> = events
+            .iter()
+            .map(|&(key, state)| keyboard.key(key, state, none(), modes))
+            .collect();
+        assert_eq!(
+            sent,
+            vec![
+                Some("\x1b[A".to_string()),
+                Some("\x1b[A".to_string()),
+                None,
+                None
+            ]
+        );
+    }
+    #[test]
+    fn repeat_is_told_apart_from_press() {
+        let mut keyboard = Keyboard::default();
+        assert_eq!(keyboard.input(A, Pressed), KeyAction::Press);
+        assert_eq!(keyboard.input(A, Pressed), KeyAction::Repeat);
+        assert_eq!(keyboard.input(B, Pressed), KeyAction::Press);
+        assert_eq!(keyboard.input(A, Released), KeyAction::Release);
+        assert_eq!(keyboard.input(A, Pressed), KeyAction::Press);
+        keyboard.clear();
+        assert_eq!(keyboard.input(B, Pressed), KeyAction::Press);
+    }
+    #[test]
+    fn characters_after_sent_keys() {
+        let mut keyboard = Keyboard::default();
+        let modes = KeyModes::default();
+        keyboard.key_event(Pressed);
+        assert!(keyboard.key(Up, Pressed, none(), modes).is_some());
+        // a press without a key code, such as a dead key or an IME commit,
+        // still has its character typed
+        keyboard.key_event(Pressed);
+        assert!(!keyboard.take_suppressed());
+        keyboard.key_event(Pressed);
+        assert!(keyboard.key(Escape, Pressed, none(), modes).is_some());
+        assert!(keyboard.take_suppressed());
+        assert!(!keyboard.take_suppressed());
+    }
+    #[test]
+    fn text_keys_are_left_to_received_character() {
+        let mut keyboard = Keyboard::default();
+        let modes = KeyModes::default();
+        assert_eq!(keyboard.key(A, Pressed, none(), modes), None);
+        assert_eq!(keyboard.key(LShift, Pressed, none(), modes), None);
+        assert_eq!(keyboard.key(Numpad1, Pressed, none(), modes), None);
+    }
+    #[test]
+    fn xterm_keys() {
+        let modes = KeyModes::default();
+        let keys = [
+            (Escape, "\x1b"),
+            (Return, "\r"),
+            (Tab, "\t"),
+            (Up, "\x1b[A"),
+            (Left, "\x1b[D"),
+            (Home, "\x1b[H"),
+            (End, "\x1b[F"),
+            (Delete, "\x1b[3~"),
+            (PageUp, "\x1b[5~"),
+            (PageDown, "\x1b[6~"),
+            (F4, "\x1bOS"),
+            (F6, "\x1b[17~"),
+            (F12, "\x1b[24~"),
+        ];
+        for &(key, sequence) in keys.iter() {
+            assert_eq!(encode(key, none(), modes).unwrap(), sequence, "{:?}", key);
+        }
+        // the keypad only has sequences of its own in application mode
+        assert_eq!(encode(Numpad5, none(), modes), None);
+        assert_eq!(encode(A, none(), modes), None);
+    }
+    #[test]
+    fn cursor_and_keypad_modes() {
+        let modes = KeyModes {
+            app_cursor: true,
+            app_keypad: true,
+            ..KeyModes::default()
+        };
+        assert_eq!(encode(Left, none(), modes).unwrap(), "\x1bOD");
+        assert_eq!(encode(Home, none(), modes).unwrap(), "\x1bOH");
+        assert_eq!(encode(Numpad1, none(), modes).unwrap(), "\x1bOq");
+        assert_eq!(encode(NumpadEnter, none(), modes).unwrap(), "\x1bOM");
+        assert_eq!(encode(Insert, none(), modes).unwrap(), "\x1b[2~");
+    }
+    #[test]
+    fn modifier_parameter() {
+        let modes = KeyModes::default();
+        assert_eq!(
+            encode(Up, ModifiersState::SHIFT, modes).unwrap(),
+            "\x1b[1;2A"
+        );
+        let ctrl_alt = ModifiersState::CTRL | ModifiersState::ALT;
+        assert_eq!(encode(F5, ctrl_alt, modes).unwrap(), "\x1b[15;7~");
+        assert_eq!(
+            encode(F1, ModifiersState::CTRL, modes).unwrap(),
+            "\x1b[1;5P"
+        );
+        assert_eq!(encode(F1, none(), modes).unwrap(), "\x1bOP");
+    }
+    #[test]
+    fn modify_other_keys_mode() {
+        let mut keyboard = Keyboard::default();
+        let modes = KeyModes {
+            modify_other_keys: true,
+            ..KeyModes::default()
+        };
+        let ctrl_shift = ModifiersState::CTRL | ModifiersState::SHIFT;
+        assert_eq!(
+            keyboard.key(A, Pressed, ctrl_shift, modes).unwrap(),
+            "\x1b[27;6;65~"
+        );
+        assert_eq!(
+            keyboard
+                .key(Return, Pressed, ModifiersState::CTRL, modes)
+                .unwrap(),
+            "\x1b[27;5;13~"
+        );
+        assert_eq!(keyboard.key(A, Pressed, ModifiersState::SHIFT, modes), None);
+        assert_eq!(keyboard.key(Up, Pressed, none(), modes).unwrap(), "\x1b[A");
+    }
+    #[test]
+    fn kitty_disambiguate() {
+        let mut keyboard = Keyboard::default();
+        let modes = KeyModes {
+            kitty_flags: KittyFlags::DISAMBIGUATE,
+            ..KeyModes::default()
+        };
+        let mut press = |key, modifiers| keyboard.key(key, Pressed, modifiers, modes);
+        assert_eq!(press(Escape, none()).unwrap(), "\x1b[27u");
+        assert_eq!(press(C, ModifiersState::CTRL).unwrap(), "\x1b[99;5u");
+        assert_eq!(press(C, ModifiersState::SHIFT), None);
+        assert_eq!(press(Return, none()).unwrap(), "\r");
+        assert_eq!(press(Return, ModifiersState::SHIFT).unwrap(), "\x1b[13;2u");
+        assert_eq!(press(Up, none()).unwrap(), "\x1b[A");
+        assert_eq!(press(Up, ModifiersState::ALT).unwrap(), "\x1b[1;3A");
+        assert_eq!(press(F3, none()).unwrap(), "\x1b[13~");
+        assert_eq!(press(Numpad1, none()).unwrap(), "\x1b[57400u");
+        assert_eq!(press(LShift, none()), None);
+        assert_eq!(keyboard.key(Up, Released, none(), modes), None);
+    }
+    #[test]
+    fn kitty_event_types_and_alternate_keys() {
+        let mut keyboard = Keyboard::default();
+        let modes = KeyModes {
+            kitty_flags: KittyFlags::all(),
+            ..KeyModes::default()
+        };
+        let ctrl_shift = ModifiersState::CTRL | ModifiersState::SHIFT;
+        let events = [(A, Pressed), (A, Pressed), (A, Released)];
+        let sent: Vec<_> = events
+            .iter()
+            .map(|&(key, state)| keyboard.key(key, state, ctrl_shift, modes).unwrap())
+            .collect();
+        assert_eq!(
+            sent,
+            vec!["\x1b[97:65;6u", "\x1b[97:65;6:2u", "\x1b[97:65;6:3u"]
+        );
+        assert_eq!(keyboard.key(A, Pressed, none(), modes).unwrap(), "\x1b[97u");
+        assert_eq!(
+            keyboard.key(Return, Pressed, none(), modes).unwrap(),
+            "\x1b[13u"
+        );
+        assert_eq!(
+            keyboard.key(LShift, Pressed, none(), modes).unwrap(),
+            "\x1b[57441u"
+        );
+        assert_eq!(
+            keyboard.key(Up, Released, none(), modes).unwrap(),
+            "\x1b[1;1:3A"
+        );
+    }
+    #[test]
+    fn editing_keys_and_typed_text() {
+        let modes = KeyModes::default();
+        assert_eq!(encode(Back, none(), modes).unwrap(), "\x7f");
+        assert_eq!(encode(Back, ModifiersState::CTRL, modes).unwrap(), "\x08");
+        assert_eq!(
+            encode(Return, ModifiersState::ALT, modes).unwrap(),
+            "\x1b\r"
+        );
+        assert_eq!(encode(Tab, ModifiersState::SHIFT, modes).unwrap(), "\x1b[Z");
+        assert_eq!(encode_char('c', ModifiersState::CTRL), "\x03");
+        assert_eq!(encode_char('\x03', ModifiersState::CTRL), "\x03");
+        assert_eq!(encode_char('[', ModifiersState::CTRL), "\x1b");
+        assert_eq!(encode_char('b', ModifiersState::ALT), "\x1bb");
+        assert_eq!(encode_char('é', none()), "é");
+    }
+}
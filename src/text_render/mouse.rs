@@ -0,0 +1,198 @@
+use winit::event::{ModifiersState, MouseButton};
+
+// Which mouse events the program wants, set with DECSET 9, 1000, 1002 and 1003.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MouseMode {
+    Off,
+    // X10 compatibility, presses without modifiers
+    Press,
+    PressRelease,
+    // motion is reported while a button is held
+    ButtonMotion,
+    AnyMotion,
+}
+// How reports are written, set with DECSET 1005, 1006, 1015 and 1016.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MouseEncoding {
+    X10,
+    Utf8,
+    Sgr,
+    Urxvt,
+    // SGR with pixel instead of cell positions
+    SgrPixels,
+}
+#[derive(Clone, Copy, Debug)]
+pub struct MouseModes {
+    pub mode: MouseMode,
+    pub encoding: MouseEncoding,
+}
+impl Default for MouseModes {
+    fn default() -> MouseModes {
+        MouseModes {
+            mode: MouseMode::Off,
+            encoding: MouseEncoding::X10,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MouseAction {
+    Press(MouseButton),
+    Release(MouseButton),
+    // with the button held down, if any
+    Motion(Option<MouseButton>),
+    WheelUp,
+    WheelDown,
+}
+// A mouse event at a cell, zero based. The pixel position is only used by
+// SGR-pixels.
+#[derive(Clone, Copy, Debug)]
+pub struct MouseReport {
+    pub action: MouseAction,
+    pub col: usize,
+    pub line: usize,
+    pub x: u32,
+    pub y: u32,
+    pub modifiers: ModifiersState,
+}
+
+// The bytes reporting an event, None when the mode does not ask for it or the
+// position does not fit the encoding.
+pub fn encode(report: &MouseReport, modes: MouseModes) -> Option<Vec<u8>> {
+    use MouseAction::*;
+    let unwanted = matches!(
+        (modes.mode, report.action),
+        (MouseMode::Off, _)
+            | (MouseMode::Press, Release(_))
+            | (MouseMode::Press, Motion(_))
+            | (MouseMode::PressRelease, Motion(_))
+            | (MouseMode::ButtonMotion, Motion(None))
+    );
+    if unwanted {
+        return None;
+    }
+    let mut code = match report.action {
+        Press(button) | Release(button) | Motion(Some(button)) => button_code(button)?,
+        Motion(None) => 3,
+        WheelUp => 64,
+        WheelDown => 65,
+    };
+    if let Motion(_) = report.action {
+        code += 32;
+    }
+    if modes.mode != MouseMode::Press {
+        let modifiers = report.modifiers;
+        code += 4 * modifiers.shift() as u32
+            + 8 * modifiers.alt() as u32
+            + 16 * modifiers.ctrl() as u32;
+    }
+    let release = matches!(report.action, Release(_));
+    // only SGR says which button was released
+    let legacy_code = if release { code | 3 } else { code };
+    let (col, line) = (report.col as u32 + 1, report.line as u32 + 1);
+    match modes.encoding {
+        MouseEncoding::Sgr | MouseEncoding::SgrPixels => {
+            let (x, y) = match modes.encoding {
+                MouseEncoding::SgrPixels => (report.x + 1, report.y + 1),
+                _ => (col, line),
+            };
+            let final_byte = if release { 'm' } else { 'M' };
+            Some(format!("\x1b[<{};{};{}{}", code, x, y, final_byte).into_bytes())
+        }
+        MouseEncoding::Urxvt => {
+            Some(format!("\x1b[{};{};{}M", 32 + legacy_code, col, line).into_bytes())
+        }
+        // each value is offset by 32 and sent as a byte
+        MouseEncoding::X10 => {
+            let mut bytes = b"\x1b[M".to_vec();
+            for &value in [legacy_code, col, line].iter() {
+                if value + 32 > 255 {
+                    return None;
+                }
+                bytes.push(value as u8 + 32);
+            }
+            Some(bytes)
+        }
+        // like X10 but the values are UTF-8 encoded, allowing larger positions
+        MouseEncoding::Utf8 => {
+            let mut text = "\x1b[M".to_string();
+            for &value in [legacy_code, col, line].iter() {
+                if value + 32 > 2047 {
+                    return None;
+                }
+                text.push(std::char::from_u32(value + 32)?);
+            }
+            Some(text.into_bytes())
+        }
+    }
+}
+fn button_code(button: MouseButton) -> Option<u32> {
+    match button {
+        MouseButton::Left => Some(0),
+        MouseButton::Middle => Some(1),
+        MouseButton::Right => Some(2),
+        MouseButton::Other(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(action: MouseAction, col: usize, line: usize) -> MouseReport {
+        MouseReport {
+            action,
+            col,
+            line,
+            x: 0,
+            y: 0,
+            modifiers: ModifiersState::empty(),
+        }
+    }
+    fn modes(mode: MouseMode, encoding: MouseEncoding) -> MouseModes {
+        MouseModes { mode, encoding }
+    }
+    #[test]
+    fn modes_filter_events() {
+        let press = report(MouseAction::Press(MouseButton::Left), 0, 0);
+        let release = report(MouseAction::Release(MouseButton::Left), 0, 0);
+        let drag = report(MouseAction::Motion(Some(MouseButton::Left)), 0, 0);
+        let motion = report(MouseAction::Motion(None), 0, 0);
+        let x10 = MouseEncoding::X10;
+        assert_eq!(encode(&press, modes(MouseMode::Off, x10)), None);
+        assert_eq!(encode(&release, modes(MouseMode::Press, x10)), None);
+        assert!(encode(&release, modes(MouseMode::PressRelease, x10)).is_some());
+        assert_eq!(encode(&drag, modes(MouseMode::PressRelease, x10)), None);
+        assert!(encode(&drag, modes(MouseMode::ButtonMotion, x10)).is_some());
+        assert_eq!(encode(&motion, modes(MouseMode::ButtonMotion, x10)), None);
+        assert!(encode(&motion, modes(MouseMode::AnyMotion, x10)).is_some());
+    }
+    #[test]
+    fn encodings() {
+        let mut press = report(MouseAction::Press(MouseButton::Right), 4, 9);
+        press.modifiers = ModifiersState::CTRL;
+        let release = report(MouseAction::Release(MouseButton::Right), 4, 9);
+        let mode = MouseMode::PressRelease;
+        let encoded = |report, encoding| encode(report, modes(mode, encoding)).unwrap();
+        assert_eq!(encoded(&press, MouseEncoding::X10), b"\x1b[M\x32\x25\x2a");
+        assert_eq!(encoded(&release, MouseEncoding::X10), b"\x1b[M\x23\x25\x2a");
+        assert_eq!(encoded(&press, MouseEncoding::Sgr), b"\x1b[<18;5;10M");
+        assert_eq!(encoded(&release, MouseEncoding::Sgr), b"\x1b[<2;5;10m");
+        assert_eq!(encoded(&press, MouseEncoding::Urxvt), b"\x1b[50;5;10M");
+        let wheel = report(MouseAction::WheelDown, 0, 0);
+        assert_eq!(encoded(&wheel, MouseEncoding::Sgr), b"\x1b[<65;1;1M");
+    }
+    #[test]
+    fn large_positions() {
+        let press = report(MouseAction::Press(MouseButton::Left), 299, 0);
+        let mode = MouseMode::PressRelease;
+        assert_eq!(encode(&press, modes(mode, MouseEncoding::X10)), None);
+        let utf8 = encode(&press, modes(mode, MouseEncoding::Utf8)).unwrap();
+        assert_eq!(utf8, "\x1b[M \u{14c}!".as_bytes());
+        let mut pixels = press;
+        pixels.x = 1000;
+        pixels.y = 20;
+        let sgr_pixels = encode(&pixels, modes(mode, MouseEncoding::SgrPixels)).unwrap();
+        assert_eq!(sgr_pixels, b"\x1b[<0;1001;21M");
+    }
+}
@@ -0,0 +1,37 @@
+use super::WindowSize;
+use crate::config::Config;
+use crate::UserEvent;
+use std::io;
+use std::process::ExitStatus;
+use winit::event_loop::EventLoopProxy;
+
+// Stands in for the process on platforms without pseudo terminals, starting
+// one always fails and the error is shown on the screen.
+pub enum ProcessManager {}
+impl ProcessManager {
+    pub fn new(
+        _config: &Config,
+        _size: WindowSize,
+        _proxy: EventLoopProxy<UserEvent>,
+    ) -> io::Result<ProcessManager> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "running programs is only supported on unix",
+        ))
+    }
+    pub fn program(&self) -> &str {
+        match *self {}
+    }
+    pub fn write(&mut self, _bytes: &[u8]) {
+        match *self {}
+    }
+    pub fn resize(&mut self, _size: WindowSize) {
+        match *self {}
+    }
+    pub fn exit_status(&mut self) -> Option<ExitStatus> {
+        match *self {}
+    }
+    pub fn read(&mut self) -> Vec<u8> {
+        match *self {}
+    }
+}
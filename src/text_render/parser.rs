@@ -0,0 +1,539 @@
+use super::utf8::Utf8Decoder;
+
+// Escape sequence parser following the DEC VT500 state machine
+// (https://vt100.net/emu/dec_ansi_parser). Input is decoded as UTF-8 first,
+// so C1 controls are recognised as the code points U+0080 to U+009F.
+const MAX_PARAMS: usize = 32;
+const MAX_INTERMEDIATES: usize = 2;
+// longer OSC, DCS and APC strings are dropped instead of buffered, this leaves
+// room for the default OSC 52 limit once base64 encoded
+const MAX_STRING: usize = 4 << 20;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Params(Vec<Vec<u16>>);
+impl Params {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    // a missing or zero parameter takes the default, as most sequences expect
+    pub fn get(&self, index: usize, default: u16) -> u16 {
+        match self.0.get(index).map(|param| param[0]) {
+            Some(0) | None => default,
+            Some(value) => value,
+        }
+    }
+    // each parameter with its colon separated sub parameters
+    pub fn iter(&self) -> impl Iterator<Item = &[u16]> {
+        self.0.iter().map(|param| param.as_slice())
+    }
+}
+impl From<Vec<Vec<u16>>> for Params {
+    fn from(params: Vec<Vec<u16>>) -> Params {
+        Params(params)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    Print(char),
+    // a C0 or C1 control character
+    Execute(u8),
+    Esc {
+        intermediates: Vec<u8>,
+        byte: u8,
+    },
+    // private markers such as '?' or '>' are kept with the intermediates
+    Csi {
+        params: Params,
+        intermediates: Vec<u8>,
+        byte: u8,
+    },
+    Osc(String),
+    Dcs {
+        params: Params,
+        intermediates: Vec<u8>,
+        byte: u8,
+        data: String,
+    },
+    Apc(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Ground,
+    Escape,
+    EscapeIntermediate,
+    CsiEntry,
+    CsiParam,
+    CsiIntermediate,
+    CsiIgnore,
+    DcsEntry,
+    DcsParam,
+    DcsIntermediate,
+    DcsPassthrough,
+    DcsIgnore,
+    OscString,
+    ApcString,
+    // SOS and PM strings are consumed but never reported
+    IgnoreString,
+}
+
+pub struct Parser {
+    state: State,
+    decoder: Utf8Decoder,
+    intermediates: Vec<u8>,
+    params: Vec<Vec<u16>>,
+    params_overflow: bool,
+    string: String,
+    string_overflow: bool,
+    // the DCS header, kept until the string is terminated
+    dcs: Option<(Params, Vec<u8>, u8)>,
+}
+impl Parser {
+    pub fn new() -> Parser {
+        Parser {
+            state: State::Ground,
+            decoder: Utf8Decoder::new(),
+            intermediates: vec![],
+            params: vec![],
+            params_overflow: false,
+            string: String::new(),
+            string_overflow: false,
+            dcs: None,
+        }
+    }
+    pub fn advance(&mut self, bytes: &[u8]) -> Vec<Action> {
+        let mut text = String::new();
+        self.decoder.decode(bytes, &mut text);
+        let mut actions = vec![];
+        for c in text.chars() {
+            self.advance_char(c, &mut actions);
+        }
+        actions
+    }
+    fn advance_char(&mut self, c: char, actions: &mut Vec<Action>) {
+        // transitions that apply from every state
+        match c {
+            '\x1b' => {
+                self.end_string(actions);
+                self.clear();
+                self.state = State::Escape;
+                return;
+            }
+            '\x18' | '\x1a' => {
+                // CAN and SUB abort the sequence, strings are dropped
+                self.string.clear();
+                self.string_overflow = false;
+                self.dcs = None;
+                self.state = State::Ground;
+                return;
+            }
+            '\u{9c}' => {
+                self.end_string(actions);
+                self.state = State::Ground;
+                return;
+            }
+            '\u{90}' | '\u{98}' | '\u{9b}' | '\u{9d}' | '\u{9e}' | '\u{9f}' => {
+                self.end_string(actions);
+                self.clear();
+                self.enter_sequence(c as u8 - 0x40);
+                return;
+            }
+            '\u{80}'..='\u{9f}' => {
+                self.end_string(actions);
+                actions.push(Action::Execute(c as u8));
+                self.state = State::Ground;
+                return;
+            }
+            _ => (),
+        }
+        match self.state {
+            State::Ground => match c {
+                '\0'..='\x1f' => actions.push(Action::Execute(c as u8)),
+                '\x7f' => (),
+                _ => actions.push(Action::Print(c)),
+            },
+            State::Escape => match c {
+                '\0'..='\x1f' => actions.push(Action::Execute(c as u8)),
+                ' '..='/' => {
+                    self.collect(c);
+                    self.state = State::EscapeIntermediate;
+                }
+                '\\' => self.state = State::Ground,
+                'P' | 'X' | '[' | ']' | '^' | '_' => self.enter_sequence(c as u8),
+                '0'..='~' => self.esc_dispatch(c, actions),
+                _ => (),
+            },
+            State::EscapeIntermediate => match c {
+                '\0'..='\x1f' => actions.push(Action::Execute(c as u8)),
+                ' '..='/' => self.collect(c),
+                '0'..='~' => self.esc_dispatch(c, actions),
+                _ => (),
+            },
+            State::CsiEntry => match c {
+                '\0'..='\x1f' => actions.push(Action::Execute(c as u8)),
+                '0'..=';' => {
+                    self.param(c);
+                    self.state = State::CsiParam;
+                }
+                '<'..='?' => {
+                    self.collect(c);
+                    self.state = State::CsiParam;
+                }
+                ' '..='/' => {
+                    self.collect(c);
+                    self.state = State::CsiIntermediate;
+                }
+                '@'..='~' => self.csi_dispatch(c, actions),
+                _ => (),
+            },
+            State::CsiParam => match c {
+                '\0'..='\x1f' => actions.push(Action::Execute(c as u8)),
+                '0'..=';' => self.param(c),
+                '<'..='?' => self.state = State::CsiIgnore,
+                ' '..='/' => {
+                    self.collect(c);
+                    self.state = State::CsiIntermediate;
+                }
+                '@'..='~' => self.csi_dispatch(c, actions),
+                _ => (),
+            },
+            State::CsiIntermediate => match c {
+                '\0'..='\x1f' => actions.push(Action::Execute(c as u8)),
+                ' '..='/' => self.collect(c),
+                '0'..='?' => self.state = State::CsiIgnore,
+                '@'..='~' => self.csi_dispatch(c, actions),
+                _ => (),
+            },
+            State::CsiIgnore => match c {
+                '\0'..='\x1f' => actions.push(Action::Execute(c as u8)),
+                '@'..='~' => self.state = State::Ground,
+                _ => (),
+            },
+            State::DcsEntry => match c {
+                '0'..=';' => {
+                    self.param(c);
+                    self.state = State::DcsParam;
+                }
+                '<'..='?' => {
+                    self.collect(c);
+                    self.state = State::DcsParam;
+                }
+                ' '..='/' => {
+                    self.collect(c);
+                    self.state = State::DcsIntermediate;
+                }
+                '@'..='~' => self.dcs_hook(c),
+                _ => (),
+            },
+            State::DcsParam => match c {
+                '0'..=';' => self.param(c),
+                '<'..='?' => self.state = State::DcsIgnore,
+                ' '..='/' => {
+                    self.collect(c);
+                    self.state = State::DcsIntermediate;
+                }
+                '@'..='~' => self.dcs_hook(c),
+                _ => (),
+            },
+            State::DcsIntermediate => match c {
+                ' '..='/' => self.collect(c),
+                '0'..='?' => self.state = State::DcsIgnore,
+                '@'..='~' => self.dcs_hook(c),
+                _ => (),
+            },
+            State::DcsPassthrough => match c {
+                '\x7f' => (),
+                _ => self.push_string(c),
+            },
+            State::OscString => match c {
+                // xterm accepts BEL as well as ST to end an OSC
+                '\x07' => {
+                    self.end_string(actions);
+                    self.state = State::Ground;
+                }
+                '\0'..='\x1f' => (),
+                _ => self.push_string(c),
+            },
+            State::ApcString => match c {
+                '\0'..='\x1f' | '\x7f' => (),
+                _ => self.push_string(c),
+            },
+            State::DcsIgnore | State::IgnoreString => (),
+        }
+    }
+    fn clear(&mut self) {
+        self.intermediates.clear();
+        self.params.clear();
+        self.params_overflow = false;
+        self.string.clear();
+        self.string_overflow = false;
+        self.dcs = None;
+    }
+    fn push_string(&mut self, c: char) {
+        if self.string_overflow {
+            return;
+        }
+        if self.string.len() + c.len_utf8() > MAX_STRING {
+            self.string_overflow = true;
+            self.string = String::new();
+        } else {
+            self.string.push(c);
+        }
+    }
+    // enters the sequence introduced by ESC followed by `byte`
+    fn enter_sequence(&mut self, byte: u8) {
+        self.state = match byte {
+            b'P' => State::DcsEntry,
+            b'[' => State::CsiEntry,
+            b']' => State::OscString,
+            b'_' => State::ApcString,
+            _ => State::IgnoreString,
+        };
+    }
+    fn collect(&mut self, c: char) {
+        if self.intermediates.len() < MAX_INTERMEDIATES {
+            self.intermediates.push(c as u8);
+        } else {
+            self.params_overflow = true;
+        }
+    }
+    fn param(&mut self, c: char) {
+        if self.params.is_empty() {
+            self.params.push(vec![0]);
+        }
+        match c {
+            ';' => {
+                if self.params.len() < MAX_PARAMS {
+                    self.params.push(vec![0]);
+                } else {
+                    self.params_overflow = true;
+                }
+            }
+            ':' => self.params.last_mut().unwrap().push(0),
+            _ => {
+                let value = self.params.last_mut().unwrap().last_mut().unwrap();
+                *value = value
+                    .saturating_mul(10)
+                    .saturating_add(c as u16 - '0' as u16);
+            }
+        }
+    }
+    fn take_params(&mut self) -> Params {
+        Params(std::mem::take(&mut self.params))
+    }
+    fn esc_dispatch(&mut self, c: char, actions: &mut Vec<Action>) {
+        if !self.params_overflow {
+            actions.push(Action::Esc {
+                intermediates: std::mem::take(&mut self.intermediates),
+                byte: c as u8,
+            });
+        }
+        self.state = State::Ground;
+    }
+    fn csi_dispatch(&mut self, c: char, actions: &mut Vec<Action>) {
+        if !self.params_overflow {
+            actions.push(Action::Csi {
+                params: self.take_params(),
+                intermediates: std::mem::take(&mut self.intermediates),
+                byte: c as u8,
+            });
+        }
+        self.state = State::Ground;
+    }
+    fn dcs_hook(&mut self, c: char) {
+        if self.params_overflow {
+            self.state = State::DcsIgnore;
+            return;
+        }
+        let params = self.take_params();
+        self.dcs = Some((params, std::mem::take(&mut self.intermediates), c as u8));
+        self.string.clear();
+        self.string_overflow = false;
+        self.state = State::DcsPassthrough;
+    }
+    fn end_string(&mut self, actions: &mut Vec<Action>) {
+        let data = std::mem::take(&mut self.string);
+        if std::mem::take(&mut self.string_overflow) {
+            self.dcs = None;
+            return;
+        }
+        match self.state {
+            State::OscString => actions.push(Action::Osc(data)),
+            State::ApcString => actions.push(Action::Apc(data)),
+            State::DcsPassthrough => {
+                if let Some((params, intermediates, byte)) = self.dcs.take() {
+                    actions.push(Action::Dcs {
+                        params,
+                        intermediates,
+                        byte,
+                        data,
+                    });
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(bytes: &[u8]) -> Vec<Action> {
+        Parser::new().advance(bytes)
+    }
+    fn csi(params: Vec<Vec<u16>>, intermediates: &[u8], byte: u8) -> Action {
+        Action::Csi {
+            params: params.into(),
+            intermediates: intermediates.to_vec(),
+            byte,
+        }
+    }
+
+    #[test]
+    fn print_and_execute() {
+        assert_eq!(
+            parse(b"a\r\n\x7fb"),
+            vec![
+                Action::Print('a'),
+                Action::Execute(b'\r'),
+                Action::Execute(b'\n'),
+                Action::Print('b'),
+            ]
+        );
+    }
+    #[test]
+    fn utf8_split_across_calls() {
+        let mut parser = Parser::new();
+        let bytes = "é€".as_bytes();
+        let mut actions = vec![];
+        for byte in bytes {
+            actions.extend(parser.advance(&[*byte]));
+        }
+        assert_eq!(actions, vec![Action::Print('é'), Action::Print('€')]);
+        assert_eq!(
+            parser.advance(b"\xffa"),
+            vec![Action::Print('\u{fffd}'), Action::Print('a')]
+        );
+    }
+    #[test]
+    fn csi_params() {
+        assert_eq!(parse(b"\x1b[m"), vec![csi(vec![], b"", b'm')]);
+        assert_eq!(
+            parse(b"\x1b[1;22H"),
+            vec![csi(vec![vec![1], vec![22]], b"", b'H')]
+        );
+        assert_eq!(
+            parse(b"\x1b[;5r"),
+            vec![csi(vec![vec![0], vec![5]], b"", b'r')]
+        );
+        assert_eq!(
+            parse(b"\x1b[99999A"),
+            vec![csi(vec![vec![65535]], b"", b'A')]
+        );
+    }
+    #[test]
+    fn csi_sub_params() {
+        assert_eq!(
+            parse(b"\x1b[38:2::10:20:30;1m"),
+            vec![csi(vec![vec![38, 2, 0, 10, 20, 30], vec![1]], b"", b'm')]
+        );
+    }
+    #[test]
+    fn csi_private_and_intermediates() {
+        assert_eq!(
+            parse(b"\x1b[?1049h"),
+            vec![csi(vec![vec![1049]], b"?", b'h')]
+        );
+        assert_eq!(parse(b"\x1b[>c"), vec![csi(vec![], b">", b'c')]);
+        assert_eq!(parse(b"\x1b[2 q"), vec![csi(vec![vec![2]], b" ", b'q')]);
+        assert_eq!(parse(b"\x1b[?2$p"), vec![csi(vec![vec![2]], b"?$", b'p')]);
+    }
+    #[test]
+    fn csi_invalid_is_ignored() {
+        assert_eq!(parse(b"\x1b[1?2hx"), vec![Action::Print('x')]);
+        assert_eq!(parse(b"\x1b[1 2hx"), vec![Action::Print('x')]);
+    }
+    #[test]
+    fn control_inside_csi() {
+        assert_eq!(
+            parse(b"\x1b[1\n2A"),
+            vec![Action::Execute(b'\n'), csi(vec![vec![12]], b"", b'A')]
+        );
+    }
+    #[test]
+    fn cancel_aborts_sequence() {
+        assert_eq!(parse(b"\x1b[12\x18A"), vec![Action::Print('A')]);
+    }
+    #[test]
+    fn c1_csi() {
+        assert_eq!(
+            parse("\u{9b}2J".as_bytes()),
+            vec![csi(vec![vec![2]], b"", b'J')]
+        );
+        assert_eq!(parse("\u{85}".as_bytes()), vec![Action::Execute(0x85)]);
+    }
+    #[test]
+    fn esc_dispatch() {
+        assert_eq!(
+            parse(b"\x1b7\x1b(B\x1b#8"),
+            vec![
+                Action::Esc {
+                    intermediates: vec![],
+                    byte: b'7'
+                },
+                Action::Esc {
+                    intermediates: vec![b'('],
+                    byte: b'B'
+                },
+                Action::Esc {
+                    intermediates: vec![b'#'],
+                    byte: b'8'
+                },
+            ]
+        );
+    }
+    #[test]
+    fn osc_terminators() {
+        assert_eq!(
+            parse(b"\x1b]0;t\xc3\xa9tle;x\x07"),
+            vec![Action::Osc("0;tétle;x".to_string())]
+        );
+        assert_eq!(
+            parse(b"\x1b]2;a\x1b\\b"),
+            vec![Action::Osc("2;a".to_string()), Action::Print('b')]
+        );
+        assert_eq!(
+            parse("\u{9d}1;b\u{9c}".as_bytes()),
+            vec![Action::Osc("1;b".to_string())]
+        );
+    }
+    #[test]
+    fn long_strings_are_dropped() {
+        let mut parser = Parser::new();
+        let mut input = b"\x1b]52;c;".to_vec();
+        input.resize(MAX_STRING + 10, b'A');
+        input.extend_from_slice(b"\x07\x1b]2;t\x07");
+        assert_eq!(parser.advance(&input), vec![Action::Osc("2;t".to_string())]);
+    }
+    #[test]
+    fn dcs() {
+        assert_eq!(
+            parse(b"\x1bP1$qm\x1b\\"),
+            vec![Action::Dcs {
+                params: vec![vec![1]].into(),
+                intermediates: vec![b'$'],
+                byte: b'q',
+                data: "m".to_string(),
+            }]
+        );
+    }
+    #[test]
+    fn apc_and_ignored_strings() {
+        assert_eq!(
+            parse(b"\x1b_Gi=1\x1b\\\x1b^pm\x1b\\\x1bXsos\x1b\\"),
+            vec![Action::Apc("Gi=1".to_string())]
+        );
+    }
+}
@@ -0,0 +1,33 @@
+// Clipboard text as the child receives it. Newlines become carriage returns
+// like a typed Enter, and other control characters are dropped so pasted text
+// can't end a bracketed paste early or send escape sequences of its own.
+pub fn encode(text: &str, bracketed: bool) -> String {
+    let text: String = text
+        .replace("\r\n", "\r")
+        .chars()
+        .map(|c| if c == '\n' { '\r' } else { c })
+        .filter(|&c| c == '\r' || c == '\t' || !c.is_control())
+        .collect();
+    if bracketed {
+        format!("\x1b[200~{}\x1b[201~", text)
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bracketed() {
+        assert_eq!(encode("ls\n", true), "\x1b[200~ls\r\x1b[201~");
+        assert_eq!(encode("a\x1b[201~b", true), "\x1b[200~a[201~b\x1b[201~");
+        assert_eq!(encode("a\u{9b}201~b", true), "\x1b[200~a201~b\x1b[201~");
+    }
+    #[test]
+    fn plain() {
+        assert_eq!(encode("one\r\ntwo\n", false), "one\rtwo\r");
+        assert_eq!(encode("a\tb\x03\x7fc é", false), "a\tbc é");
+    }
+}
@@ -1,44 +1,190 @@
+use super::pty::Pty;
+use super::WindowSize;
+use crate::config::Config;
+use crate::UserEvent;
+use std::ffi::CStr;
+use std::fs::File;
+use std::io;
 use std::io::Read;
 use std::io::Write;
-use std::process::{ChildStdout, Command, Stdio};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use winit::event_loop::EventLoopProxy;
 pub struct ProcessManager {
-    stdin: std::process::ChildStdin,
+    pty: Pty,
+    // the program that was started, as given in the config or $SHELL
+    program: String,
 
-    stdout_reciever: Receiver<String>,
+    stdout_reciever: Receiver<Vec<u8>>,
+    // input is written on its own thread, a child that is not reading would
+    // otherwise block the event loop on a large paste
+    stdin_sender: Sender<Vec<u8>>,
+    exit_status: Option<ExitStatus>,
+    // set while a wakeup is queued on the event loop that has not been read yet
+    wakeup_pending: Arc<AtomicBool>,
 }
 impl ProcessManager {
-    pub fn new() -> ProcessManager {
-        let mut p = Command::new("powershell")
-            .stdout(Stdio::piped())
-            .stdin(Stdio::piped())
-            .spawn()
-            .expect("failed to launch powershell");
+    pub fn new(
+        config: &Config,
+        size: WindowSize,
+        proxy: EventLoopProxy<UserEvent>,
+    ) -> io::Result<ProcessManager> {
+        let (program, command) = shell_command(config);
+        let pty = Pty::spawn(command, size).map_err(|e| {
+            io::Error::new(e.kind(), format!("failed to launch {}: {}", program, e))
+        })?;
         let (stdout_sender, stdout_reciever) = channel();
-        let mut stdout = p.stdout.unwrap();
+        let mut master = pty.master().try_clone()?;
+        let (stdin_sender, stdin_reciever) = channel();
+        let mut stdin = pty.master().try_clone()?;
+        std::thread::spawn(move || write(&mut stdin, stdin_reciever));
+        let wakeup_pending = Arc::new(AtomicBool::new(false));
+        let waker = Waker {
+            proxy,
+            pending: wakeup_pending.clone(),
+        };
         std::thread::spawn(move || {
-            read(&mut stdout, stdout_sender);
+            read(&mut master, stdout_sender, &waker);
+            // lets the event loop notice that the child is gone
+            waker.wake();
         });
-        ProcessManager {
-            stdin: p.stdin.unwrap(),
+        Ok(ProcessManager {
+            pty,
+            program,
             stdout_reciever,
+            stdin_sender,
+            exit_status: None,
+            wakeup_pending,
+        })
+    }
+    pub fn program(&self) -> &str {
+        &self.program
+    }
+    // queues input for the child, it is written in order as the child reads it
+    pub fn write(&mut self, bytes: &[u8]) {
+        if self.stdin_sender.send(bytes.to_vec()).is_err() {
+            eprintln!("failed to write to child: its input is closed");
         }
     }
-    pub fn write(&mut self, to_write: String) {
-        self.stdin.write(to_write.as_bytes());
+    pub fn resize(&mut self, size: WindowSize) {
+        if let Err(e) = self.pty.resize(size) {
+            eprintln!("failed to resize pty: {}", e);
+        }
+    }
+    // reaps the child if it has exited, the status is kept for later calls
+    pub fn exit_status(&mut self) -> Option<ExitStatus> {
+        if self.exit_status.is_none() {
+            match self.pty.child().try_wait() {
+                Ok(status) => self.exit_status = status,
+                Err(e) => eprintln!("failed to wait on child: {}", e),
+            }
+        }
+        self.exit_status
     }
-    pub fn read(&mut self) -> String {
+    // everything the child wrote since the last call, never blocks
+    pub fn read(&mut self) -> Vec<u8> {
+        // cleared before draining so data sent after this point wakes the loop again
+        self.wakeup_pending.store(false, Ordering::SeqCst);
         self.stdout_reciever
             .try_iter()
-            .fold(String::new(), |sum, s| sum + &s)
+            .fold(vec![], |mut sum, bytes| {
+                sum.extend_from_slice(&bytes);
+                sum
+            })
     }
 }
-fn read(std_out: &mut ChildStdout, send: Sender<String>) {
-    const BUFFER_SIZE: usize = 10;
+// Picks the program to run: the configured command, then $SHELL, then the
+// shell from the passwd entry and finally /bin/sh.
+fn shell_command(config: &Config) -> (String, Command) {
+    if let Some(args) = &config.command {
+        let mut command = Command::new(&args[0]);
+        command.args(&args[1..]);
+        return (args[0].clone(), command);
+    }
+    let program = std::env::var("SHELL")
+        .ok()
+        .filter(|shell| !shell.is_empty())
+        .or_else(passwd_shell)
+        .unwrap_or_else(|| "/bin/sh".to_string());
+    let mut command = Command::new(&program);
+    if config.login_shell {
+        // a leading dash in argv[0] asks the shell to act as a login shell
+        let name = Path::new(&program)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| program.clone());
+        command.arg0(format!("-{}", name));
+    }
+    (program, command)
+}
+fn passwd_shell() -> Option<String> {
+    let mut buffer = vec![0; 4096];
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result = std::ptr::null_mut();
+    let ret = unsafe {
+        libc::getpwuid_r(
+            libc::getuid(),
+            &mut passwd,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        )
+    };
+    if ret != 0 || result.is_null() || passwd.pw_shell.is_null() {
+        return None;
+    }
+    let shell = unsafe { CStr::from_ptr(passwd.pw_shell) }
+        .to_string_lossy()
+        .into_owned();
+    if shell.is_empty() {
+        None
+    } else {
+        Some(shell)
+    }
+}
+struct Waker {
+    proxy: EventLoopProxy<UserEvent>,
+    pending: Arc<AtomicBool>,
+}
+impl Waker {
+    // bursts of output only queue a single redraw until the main thread reads them
+    fn wake(&self) {
+        if !self.pending.swap(true, Ordering::SeqCst) {
+            let _ = self.proxy.send_event(UserEvent::ProcessOutput);
+        }
+    }
+}
+fn write(std_in: &mut File, receive: Receiver<Vec<u8>>) {
+    for bytes in receive {
+        if let Err(e) = std_in.write_all(&bytes) {
+            // linux reports EIO once the slave side has been closed
+            if e.raw_os_error() != Some(libc::EIO) {
+                eprintln!("failed to write to child: {}", e);
+            }
+            return;
+        }
+    }
+}
+fn read(std_out: &mut File, send: Sender<Vec<u8>>, waker: &Waker) {
+    const BUFFER_SIZE: usize = 4096;
+    let mut buff = [0; BUFFER_SIZE];
     loop {
-        let mut buff = [0; BUFFER_SIZE];
-        std_out.read_exact(&mut buff);
-        let s = unsafe { String::from_utf8_unchecked(buff.to_vec()) };
-        send.send(s);
+        // returns as soon as anything is available instead of waiting for a full buffer
+        match std_out.read(&mut buff) {
+            Ok(0) => return,
+            Ok(n) => {
+                if send.send(buff[..n].to_vec()).is_err() {
+                    return;
+                }
+                waker.wake();
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            // linux reports EIO once the slave side has been closed
+            Err(_) => return,
+        }
     }
 }
@@ -0,0 +1,97 @@
+use super::WindowSize;
+use std::fs::File;
+use std::io;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+use std::ptr;
+
+// A pseudo terminal with a child process attached to its slave side. All io
+// with the child goes through the master fd.
+pub struct Pty {
+    master: File,
+    child: Child,
+}
+impl Pty {
+    pub fn spawn(mut command: Command, size: WindowSize) -> io::Result<Pty> {
+        let (master, slave) = open_pty(size)?;
+        // the child gets its own copies of the slave as stdin, stdout and
+        // stderr, neither fd is left open in the programs it starts
+        set_cloexec(master)?;
+        set_cloexec(slave)?;
+        let master = unsafe { File::from_raw_fd(master) };
+        let slave = unsafe { File::from_raw_fd(slave) };
+        command
+            .stdin(Stdio::from(slave.try_clone()?))
+            .stdout(Stdio::from(slave.try_clone()?))
+            .stderr(Stdio::from(slave))
+            .env("TERM", "xterm-256color");
+        unsafe {
+            command.pre_exec(|| {
+                // become a session leader so the slave can be our controlling terminal
+                if libc::setsid() == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                if libc::ioctl(0, libc::TIOCSCTTY as _, 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        // the command holds the only copies of the slave fd, they are closed
+        // in the parent once it goes out of scope
+        let child = command.spawn()?;
+        Ok(Pty { master, child })
+    }
+    // the kernel sends SIGWINCH to the foreground process group when the size changes
+    pub fn resize(&self, size: WindowSize) -> io::Result<()> {
+        let winsize = winsize(size);
+        let ret = unsafe { libc::ioctl(self.master.as_raw_fd(), libc::TIOCSWINSZ, &winsize) };
+        if ret == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+    pub fn master(&self) -> &File {
+        &self.master
+    }
+    pub fn child(&mut self) -> &mut Child {
+        &mut self.child
+    }
+}
+fn open_pty(size: WindowSize) -> io::Result<(RawFd, RawFd)> {
+    let winsize = winsize(size);
+    let mut master: libc::c_int = -1;
+    let mut slave: libc::c_int = -1;
+    let ret = unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            &winsize,
+        )
+    };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((master, slave))
+}
+fn winsize(size: WindowSize) -> libc::winsize {
+    libc::winsize {
+        ws_row: size.rows,
+        ws_col: size.cols,
+        ws_xpixel: size.pixel_width,
+        ws_ypixel: size.pixel_height,
+    }
+}
+fn set_cloexec(fd: RawFd) -> io::Result<()> {
+    let ret = unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFD);
+        libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC)
+    };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
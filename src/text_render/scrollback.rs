@@ -0,0 +1,47 @@
+use super::grid::{Cell, Row};
+use std::collections::VecDeque;
+
+// Lines that scrolled off the top of the primary screen, oldest first. Rows
+// that end a line are stored without their trailing blank cells.
+pub struct Scrollback {
+    rows: VecDeque<Row>,
+    limit: usize,
+}
+impl Scrollback {
+    pub fn new(limit: usize) -> Scrollback {
+        Scrollback {
+            rows: VecDeque::new(),
+            limit,
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+    pub fn push(&mut self, mut row: Row) {
+        if self.limit == 0 {
+            return;
+        }
+        // wrapped rows keep their trailing spaces so they can be joined again
+        if !row.wrapped() {
+            row.trim(Cell::default());
+        }
+        if self.rows.len() == self.limit {
+            self.rows.pop_front();
+        }
+        self.rows.push_back(row);
+    }
+    // index 0 is the most recent line
+    pub fn get(&self, index: usize) -> &Row {
+        &self.rows[self.rows.len() - 1 - index]
+    }
+    pub fn clear(&mut self) {
+        self.rows.clear();
+    }
+    // removes every row, oldest first
+    pub fn take(&mut self) -> Vec<Row> {
+        self.rows.drain(..).collect()
+    }
+}
@@ -0,0 +1,235 @@
+use super::term::Term;
+
+// A cell position, lines are numbered the way `Term::line` takes them so a
+// point stays on the same text while the screen scrolls.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Point {
+    pub line: usize,
+    pub col: usize,
+}
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SelectionKind {
+    Simple,
+    // whole words, split at the configured separators
+    Word,
+    Line,
+    // a rectangle between the two corners
+    Block,
+}
+// The selected cells from start to end inclusive, for a block only the
+// columns between those of start and end.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SelectionRange {
+    pub start: Point,
+    pub end: Point,
+    pub block: bool,
+}
+impl SelectionRange {
+    pub fn contains(&self, point: Point) -> bool {
+        if point.line < self.start.line || point.line > self.end.line {
+            return false;
+        }
+        if self.block {
+            return point.col >= self.start.col && point.col <= self.end.col;
+        }
+        (point.line > self.start.line || point.col >= self.start.col)
+            && (point.line < self.end.line || point.col <= self.end.col)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Selection {
+    kind: SelectionKind,
+    // where the mouse was pressed and where it is now
+    anchor: Point,
+    end: Point,
+}
+impl Selection {
+    pub fn new(kind: SelectionKind, point: Point) -> Selection {
+        Selection {
+            kind,
+            anchor: point,
+            end: point,
+        }
+    }
+    pub fn update(&mut self, point: Point) {
+        self.end = point;
+    }
+    // None for a click that did not drag over anything
+    pub fn range(&self, term: &Term, separators: &str) -> Option<SelectionRange> {
+        let (start, end) = if self.anchor <= self.end {
+            (self.anchor, self.end)
+        } else {
+            (self.end, self.anchor)
+        };
+        let last_col = term.grid().cols() - 1;
+        let (start, end) = match self.kind {
+            SelectionKind::Simple | SelectionKind::Block if self.anchor == self.end => return None,
+            SelectionKind::Simple => (start, end),
+            SelectionKind::Block => {
+                let cols = (
+                    self.anchor.col.min(self.end.col),
+                    self.anchor.col.max(self.end.col),
+                );
+                return Some(SelectionRange {
+                    start: Point {
+                        line: start.line,
+                        col: cols.0,
+                    },
+                    end: Point {
+                        line: end.line,
+                        col: cols.1,
+                    },
+                    block: true,
+                });
+            }
+            SelectionKind::Word => (
+                word_edge(term, start, separators, false),
+                word_edge(term, end, separators, true),
+            ),
+            SelectionKind::Line => (
+                Point { col: 0, ..start },
+                Point {
+                    col: last_col,
+                    ..end
+                },
+            ),
+        };
+        Some(SelectionRange {
+            start,
+            end,
+            block: false,
+        })
+    }
+    // the selected text, lines end in newlines unless they were soft wrapped
+    pub fn text(&self, term: &Term, separators: &str) -> String {
+        let range = match self.range(term, separators) {
+            Some(range) => range,
+            None => return String::new(),
+        };
+        let mut text = String::new();
+        for line in range.start.line..=range.end.line {
+            let row = match term.line(line) {
+                Some(row) => row,
+                None => continue,
+            };
+            let cells = row.cells();
+            let first = if range.block || line == range.start.line {
+                range.start.col
+            } else {
+                0
+            };
+            let last = if range.block || line == range.end.line {
+                range.end.col + 1
+            } else {
+                cells.len()
+            };
+            let selected: String = cells[first.min(cells.len())..last.min(cells.len())]
+                .iter()
+                .map(|cell| cell.c)
+                .collect();
+            text.push_str(selected.trim_end());
+            let ends_line = range.block || !row.wrapped() || line == range.end.line;
+            if line != range.end.line && ends_line {
+                text.push('\n');
+            }
+        }
+        text
+    }
+}
+// the first or last column of the word around a point
+fn word_edge(term: &Term, point: Point, separators: &str, forward: bool) -> Point {
+    let cells = match term.line(point.line) {
+        Some(row) => row.cells(),
+        None => return point,
+    };
+    let in_word = |col: usize| {
+        cells
+            .get(col)
+            .is_some_and(|cell| !cell.c.is_whitespace() && !separators.contains(cell.c))
+    };
+    let mut col = point.col;
+    // a separator or blank is a word of its own
+    if !in_word(col) {
+        return point;
+    }
+    if forward {
+        while in_word(col + 1) {
+            col += 1;
+        }
+    } else {
+        while col > 0 && in_word(col - 1) {
+            col -= 1;
+        }
+    }
+    Point { col, ..point }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(line: usize, col: usize) -> Point {
+        Point { line, col }
+    }
+    fn select(term: &Term, kind: SelectionKind, from: Point, to: Point) -> String {
+        let mut selection = Selection::new(kind, from);
+        selection.update(to);
+        selection.text(term, ",()")
+    }
+    #[test]
+    fn simple_and_block() {
+        let mut term = Term::new(6, 3, 10);
+        term.advance(b"abc\r\ndefgh\r\nij");
+        assert_eq!(
+            select(&term, SelectionKind::Simple, point(0, 1), point(1, 2)),
+            "bc\ndef"
+        );
+        assert_eq!(
+            select(&term, SelectionKind::Simple, point(1, 2), point(0, 1)),
+            "bc\ndef"
+        );
+        assert_eq!(
+            select(&term, SelectionKind::Block, point(0, 1), point(2, 2)),
+            "bc\nef\nj"
+        );
+        assert_eq!(
+            select(&term, SelectionKind::Simple, point(0, 1), point(0, 1)),
+            ""
+        );
+    }
+    #[test]
+    fn words_and_lines() {
+        let mut term = Term::new(12, 2, 10);
+        term.advance(b"foo(bar baz)\r\nqux");
+        assert_eq!(
+            select(&term, SelectionKind::Word, point(0, 5), point(0, 5)),
+            "bar"
+        );
+        assert_eq!(
+            select(&term, SelectionKind::Word, point(0, 1), point(0, 9)),
+            "foo(bar baz"
+        );
+        assert_eq!(
+            select(&term, SelectionKind::Line, point(0, 5), point(1, 0)),
+            "foo(bar baz)\nqux"
+        );
+    }
+    #[test]
+    fn wrapped_lines_and_scrolling() {
+        let mut term = Term::new(4, 2, 10);
+        term.advance(b"abcdef");
+        assert_eq!(
+            select(&term, SelectionKind::Line, point(0, 0), point(1, 0)),
+            "abcdef"
+        );
+        let selection = {
+            let mut selection = Selection::new(SelectionKind::Simple, point(0, 0));
+            selection.update(point(0, 3));
+            selection
+        };
+        // the selection follows its text into the scrollback
+        term.advance(b"\r\n\r\n");
+        assert_eq!(selection.text(&term, ""), "abcd");
+    }
+}
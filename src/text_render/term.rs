@@ -0,0 +1,1566 @@
+use super::clipboard::ClipboardKind;
+use super::grid::{Cell, Color, Flags, Grid, Region, Row};
+use super::keyboard::{KeyModes, KittyFlags};
+use super::mouse::{MouseEncoding, MouseMode, MouseModes};
+use super::parser::{Action, Params, Parser};
+use super::scrollback::Scrollback;
+
+const TAB_WIDTH: usize = 8;
+// entries beyond this push the oldest kitty keyboard flags out
+const KITTY_STACK_LIMIT: usize = 16;
+// titles pushed with CSI 22 t beyond this are dropped, as in xterm
+const TITLE_STACK_LIMIT: usize = 10;
+// reported by XTVERSION and secondary DA
+const NAME: &str = env!("CARGO_PKG_NAME");
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Cursor {
+    pub line: usize,
+    pub col: usize,
+    // set after printing into the last column, the next printed character
+    // wraps to a new line first
+    pending_wrap: bool,
+    // colours and attributes given to newly printed cells
+    template: Cell,
+}
+// the state stored by DECSC
+#[derive(Clone, Copy, Debug, Default)]
+struct SavedCursor {
+    cursor: Cursor,
+    origin_mode: bool,
+}
+
+// The window title and icon name set by OSC 0, 1 and 2, None until a program
+// sets them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Title {
+    pub title: Option<String>,
+    // winit has no way to set an icon name, it is only kept so the title
+    // stack saves and restores it like xterm
+    pub icon_name: Option<String>,
+}
+
+// OSC 52 clipboard access asked for by the program, the caller decides
+// whether to allow it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClipboardRequest {
+    Store(ClipboardKind, String),
+    // the reply names the selection the way the request did and goes at
+    // the given offset into the responses so it keeps its place among them
+    Load(ClipboardKind, String, usize),
+}
+
+// The terminal screen model, the output of the child is fed in through
+// `advance` and drawn from `grid`.
+pub struct Term {
+    grid: Grid,
+    // the screen that is not being shown, the primary screen while the
+    // alternate one is active and the other way around
+    inactive_grid: Grid,
+    alt_screen: bool,
+    scrollback: Scrollback,
+    // how many lines the view is scrolled back into the scrollback
+    display_offset: usize,
+    // rows ever pushed into the scrollback, see `line`
+    scrolled: usize,
+    cursor: Cursor,
+    parser: Parser,
+    autowrap: bool,
+    // scrolling happens inside the margins set by DECSTBM and DECSLRM
+    margins: Region,
+    // DECOM, cursor positions are relative to the margins
+    origin_mode: bool,
+    // DECLRMM, allows DECSLRM to set the left and right margins
+    lr_margin_mode: bool,
+    saved_cursor: Option<SavedCursor>,
+    // each screen has its own saved cursor
+    inactive_saved_cursor: Option<SavedCursor>,
+    // repeated by REP
+    last_printed: Option<char>,
+    key_modes: KeyModes,
+    mouse_modes: MouseModes,
+    // pasted text is wrapped in CSI 200 ~ and CSI 201 ~
+    bracketed_paste: bool,
+    // DECSET 1007, the mouse wheel sends cursor keys on the alternate screen
+    alternate_scroll: bool,
+    // kitty keyboard flags pushed by the program, each screen has its own stack
+    kitty_flags: Vec<KittyFlags>,
+    inactive_kitty_flags: Vec<KittyFlags>,
+    // replies to queries, written back to the child
+    responses: String,
+    clipboard_requests: Vec<ClipboardRequest>,
+    title: Title,
+    // saved by CSI 22 t and restored by CSI 23 t
+    title_stack: Vec<Title>,
+    // set when the window title needs updating
+    title_changed: bool,
+    // the size of a cell in pixels for CSI 14 t and 16 t, zero until set
+    cell_width: usize,
+    cell_height: usize,
+}
+impl Term {
+    pub fn new(cols: usize, lines: usize, scrollback_lines: usize) -> Term {
+        let grid = Grid::new(cols, lines);
+        Term {
+            margins: grid.full_region(),
+            grid,
+            inactive_grid: Grid::new(cols, lines),
+            alt_screen: false,
+            scrollback: Scrollback::new(scrollback_lines),
+            display_offset: 0,
+            scrolled: 0,
+            cursor: Cursor::default(),
+            parser: Parser::new(),
+            autowrap: true,
+            origin_mode: false,
+            lr_margin_mode: false,
+            saved_cursor: None,
+            inactive_saved_cursor: None,
+            last_printed: None,
+            key_modes: KeyModes::default(),
+            mouse_modes: MouseModes::default(),
+            bracketed_paste: false,
+            alternate_scroll: false,
+            kitty_flags: vec![],
+            inactive_kitty_flags: vec![],
+            responses: String::new(),
+            clipboard_requests: vec![],
+            title: Title::default(),
+            title_stack: vec![],
+            title_changed: true,
+            cell_width: 0,
+            cell_height: 0,
+        }
+    }
+    pub fn grid(&self) -> &Grid {
+        &self.grid
+    }
+    pub fn cursor(&self) -> &Cursor {
+        &self.cursor
+    }
+    pub fn key_modes(&self) -> KeyModes {
+        KeyModes {
+            kitty_flags: self.kitty_flags.last().copied().unwrap_or_default(),
+            ..self.key_modes
+        }
+    }
+    pub fn mouse_modes(&self) -> MouseModes {
+        self.mouse_modes
+    }
+    pub fn bracketed_paste(&self) -> bool {
+        self.bracketed_paste
+    }
+    pub fn alternate_scroll(&self) -> bool {
+        self.alternate_scroll
+    }
+    // everything the terminal has to answer since the last call
+    pub fn take_responses(&mut self) -> String {
+        std::mem::take(&mut self.responses)
+    }
+    pub fn take_clipboard_requests(&mut self) -> Vec<ClipboardRequest> {
+        std::mem::take(&mut self.clipboard_requests)
+    }
+    pub fn set_cell_size(&mut self, width: usize, height: usize) {
+        self.cell_width = width;
+        self.cell_height = height;
+    }
+    pub fn title(&self) -> &Title {
+        &self.title
+    }
+    // whether the title changed since the last call
+    pub fn take_title_changed(&mut self) -> bool {
+        std::mem::take(&mut self.title_changed)
+    }
+    fn set_title(&mut self, title: Option<String>) {
+        if title != self.title.title {
+            self.title.title = title;
+            self.title_changed = true;
+        }
+    }
+    pub fn display_offset(&self) -> usize {
+        self.display_offset
+    }
+    // the row shown on a line of the screen, rows from the scrollback can be
+    // shorter than the grid
+    pub fn display_row(&self, line: usize) -> &Row {
+        if line < self.display_offset {
+            self.scrollback.get(self.display_offset - 1 - line)
+        } else {
+            &self.grid.rows()[line - self.display_offset]
+        }
+    }
+    // Lines numbered from the first one that ever scrolled into the
+    // scrollback, so a number keeps pointing at the same text while the
+    // screen scrolls. Lines dropped from the scrollback are None.
+    pub fn line(&self, line: usize) -> Option<&Row> {
+        if line >= self.scrolled {
+            return self.grid.rows().get(line - self.scrolled);
+        }
+        let index = self.scrolled - 1 - line;
+        if index < self.scrollback.len() {
+            Some(self.scrollback.get(index))
+        } else {
+            None
+        }
+    }
+    // the number `line` takes for a line of the view
+    pub fn visible_line(&self, line: usize) -> usize {
+        self.scrolled - self.display_offset + line
+    }
+    pub fn alt_screen(&self) -> bool {
+        self.alt_screen
+    }
+    // positive values scroll back into the history, the alternate screen has
+    // none of its own
+    pub fn scroll_display(&mut self, lines: isize) {
+        if self.alt_screen {
+            return;
+        }
+        let offset = self.display_offset as isize + lines;
+        self.display_offset = offset.max(0).min(self.scrollback.len() as isize) as usize;
+    }
+    pub fn scroll_to_bottom(&mut self) {
+        self.display_offset = 0;
+    }
+    pub fn advance(&mut self, bytes: &[u8]) {
+        for action in self.parser.advance(bytes) {
+            self.perform(action);
+        }
+    }
+    // The primary screen and the scrollback are re-wrapped to the new width,
+    // the alternate screen is cut off as its programs redraw it anyway.
+    pub fn resize(&mut self, cols: usize, lines: usize) {
+        let history = self.scrollback.len();
+        if self.alt_screen {
+            // the primary screen's cursor was saved when switching away from it
+            let mut cursor = match self.inactive_saved_cursor {
+                Some(saved) => saved.cursor,
+                None => self.cursor,
+            };
+            reflow(
+                &mut self.scrollback,
+                &mut self.inactive_grid,
+                &mut cursor,
+                cols,
+                lines,
+            );
+            if let Some(saved) = &mut self.inactive_saved_cursor {
+                saved.cursor = cursor;
+            }
+            self.grid.resize(cols, lines);
+            self.cursor.line = self.cursor.line.min(lines - 1);
+            self.cursor.col = self.cursor.col.min(cols - 1);
+            self.cursor.pending_wrap = false;
+        } else {
+            reflow(
+                &mut self.scrollback,
+                &mut self.grid,
+                &mut self.cursor,
+                cols,
+                lines,
+            );
+            self.inactive_grid.resize(cols, lines);
+        }
+        self.scrolled = self.scrolled - history + self.scrollback.len();
+        self.margins = self.grid.full_region();
+        self.display_offset = self.display_offset.min(self.scrollback.len());
+    }
+    fn perform(&mut self, action: Action) {
+        match action {
+            Action::Print(c) => self.print(c),
+            Action::Execute(byte) => self.execute(byte),
+            Action::Csi {
+                params,
+                intermediates,
+                byte,
+            } => self.csi(&params, &intermediates, byte),
+            Action::Esc {
+                intermediates,
+                byte,
+            } => self.esc(&intermediates, byte),
+            Action::Osc(data) => self.osc(&data),
+            Action::Dcs {
+                intermediates,
+                byte,
+                data,
+                ..
+            } => self.dcs(&intermediates, byte, &data),
+            _ => (),
+        }
+    }
+    fn osc(&mut self, data: &str) {
+        let mut parts = data.splitn(2, ';');
+        let (command, data) = match (parts.next(), parts.next()) {
+            (Some(command), Some(data)) => (command, data),
+            _ => return,
+        };
+        // control characters are dropped so a title can't garble the title bar
+        let text = || Some(data.chars().filter(|c| !c.is_control()).collect());
+        match command {
+            "0" => {
+                self.set_title(text());
+                self.title.icon_name = text();
+            }
+            "1" => self.title.icon_name = text(),
+            "2" => self.set_title(text()),
+            "52" => self.osc52(data),
+            _ => (),
+        }
+    }
+    fn dcs(&mut self, intermediates: &[u8], byte: u8, data: &str) {
+        // DECRQSS, the settings are reported in the form that sets them
+        if let (b"$", b'q') = (intermediates, byte) {
+            let margins = self.margins;
+            let setting = match data {
+                "m" => Some(format!("{}m", sgr_params(&self.cursor.template))),
+                "r" => Some(format!("{};{}r", margins.top + 1, margins.bottom)),
+                "s" => Some(format!("{};{}s", margins.left + 1, margins.right)),
+                _ => None,
+            };
+            let response = match setting {
+                Some(setting) => format!("\x1bP1$r{}\x1b\\", setting),
+                None => "\x1bP0$r\x1b\\".to_string(),
+            };
+            self.responses.push_str(&response);
+        }
+    }
+    // OSC 52 ; selections ; base64 text, or ? to read it
+    fn osc52(&mut self, data: &str) {
+        let mut parts = data.splitn(2, ';');
+        let (selection, data) = match (parts.next(), parts.next()) {
+            (Some(selection), Some(data)) => (selection, data),
+            _ => return,
+        };
+        // the primary selection when only it is named, otherwise the clipboard
+        let kind = if selection.contains('p') && !selection.contains('c') {
+            ClipboardKind::Primary
+        } else {
+            ClipboardKind::Clipboard
+        };
+        let request = if data == "?" {
+            ClipboardRequest::Load(kind, selection.to_string(), self.responses.len())
+        } else {
+            // anything that is not valid base64 clears the selection
+            let text = base64::decode(data)
+                .ok()
+                .and_then(|bytes| String::from_utf8(bytes).ok())
+                .unwrap_or_default();
+            ClipboardRequest::Store(kind, text)
+        };
+        self.clipboard_requests.push(request);
+    }
+    fn print(&mut self, c: char) {
+        if self.cursor.pending_wrap {
+            // only text running into the edge of the screen is a soft wrap
+            if self.cursor.col + 1 == self.grid.cols() {
+                self.grid.row_mut(self.cursor.line).set_wrapped(true);
+            }
+            self.carriage_return();
+            self.linefeed();
+        }
+        self.last_printed = Some(c);
+        let mut cell = self.cursor.template;
+        cell.c = c;
+        *self.grid.cell_mut(self.cursor.line, self.cursor.col) = cell;
+        // text wraps at the right margin unless it was printed beyond it
+        let right = if self.cursor.col < self.margins.right {
+            self.margins.right
+        } else {
+            self.grid.cols()
+        };
+        if self.cursor.col + 1 < right {
+            self.cursor.col += 1;
+        } else if self.autowrap {
+            self.cursor.pending_wrap = true;
+        }
+    }
+    fn execute(&mut self, byte: u8) {
+        match byte {
+            b'\x08' => {
+                self.cursor.col = self.cursor.col.saturating_sub(1);
+                self.cursor.pending_wrap = false;
+            }
+            b'\t' => {
+                let next = (self.cursor.col / TAB_WIDTH + 1) * TAB_WIDTH;
+                self.cursor.col = next.min(self.grid.cols() - 1);
+                self.cursor.pending_wrap = false;
+            }
+            b'\n' | b'\x0b' | b'\x0c' | 0x84 => self.linefeed(),
+            b'\r' => self.carriage_return(),
+            0x85 => {
+                self.carriage_return();
+                self.linefeed();
+            }
+            0x8d => self.reverse_index(),
+            _ => (),
+        }
+    }
+    fn esc(&mut self, intermediates: &[u8], byte: u8) {
+        match (intermediates, byte) {
+            (b"", b'7') => self.save_cursor(),
+            (b"", b'8') => self.restore_cursor(),
+            (b"", b'D') => self.linefeed(),
+            (b"", b'E') => {
+                self.carriage_return();
+                self.linefeed();
+            }
+            (b"", b'M') => self.reverse_index(),
+            (b"", b'=') => self.key_modes.app_keypad = true,
+            (b"", b'>') => self.key_modes.app_keypad = false,
+            _ => (),
+        }
+    }
+    fn csi(&mut self, params: &Params, intermediates: &[u8], byte: u8) {
+        let n = params.get(0, 1) as usize;
+        let line = self.cursor.line;
+        let col = self.cursor.col;
+        let margins = self.margins;
+        let (cols, lines) = (self.grid.cols(), self.grid.lines());
+        // relative movement stops at a margin when the cursor starts inside it
+        let top = if line >= margins.top { margins.top } else { 0 };
+        let bottom = if line < margins.bottom {
+            margins.bottom
+        } else {
+            lines
+        };
+        let left = if col >= margins.left { margins.left } else { 0 };
+        let right = if col < margins.right {
+            margins.right
+        } else {
+            cols
+        };
+        match (intermediates, byte) {
+            (b"", b'A') => self.goto(line.saturating_sub(n).max(top), col),
+            (b"", b'B') => self.goto((line + n).min(bottom - 1), col),
+            (b"", b'C') => self.goto(line, (col + n).min(right - 1)),
+            (b"", b'D') => self.goto(line, col.saturating_sub(n).max(left)),
+            (b"", b'G') | (b"", b'`') => self.goto_origin(None, Some(n - 1)),
+            (b"", b'd') => self.goto_origin(Some(n - 1), None),
+            (b"", b'H') | (b"", b'f') => {
+                self.goto_origin(Some(n - 1), Some(params.get(1, 1) as usize - 1));
+            }
+            (b"", b'J') => self.erase_display(params.get(0, 0)),
+            (b"", b'K') => self.erase_line(params.get(0, 0)),
+            (b"", b'@') if self.in_margins(line, col) => {
+                let blank = self.blank();
+                self.grid
+                    .row_mut(line)
+                    .insert_cells(col, margins.right, n, blank);
+            }
+            (b"", b'P') if self.in_margins(line, col) => {
+                let blank = self.blank();
+                self.grid
+                    .row_mut(line)
+                    .delete_cells(col, margins.right, n, blank);
+            }
+            (b"", b'X') => {
+                let blank = self.blank();
+                self.grid.row_mut(line).erase(col, col + n, blank);
+            }
+            // IL and DL only act inside the scroll region
+            (b"", b'L') if self.in_margins(line, col) => {
+                let blank = self.blank();
+                let region = Region {
+                    top: line,
+                    ..margins
+                };
+                self.grid.scroll_down(region, n, blank);
+                self.goto(line, margins.left);
+            }
+            (b"", b'M') if self.in_margins(line, col) => {
+                let blank = self.blank();
+                let region = Region {
+                    top: line,
+                    ..margins
+                };
+                self.grid.scroll_up(region, n, blank);
+                self.goto(line, margins.left);
+            }
+            (b"", b'S') => self.scroll_up(n),
+            (b"", b'T') => self.scroll_down(n),
+            (b"", b'm') => self.sgr(params),
+            // XTMODKEYS, only modifyOtherKeys mode 2 is supported
+            (b">", b'm') if params.get(0, 0) == 4 => {
+                self.key_modes.modify_other_keys = params.get(1, 0) == 2;
+            }
+            (b"", b'r') => {
+                let top = params.get(0, 1) as usize - 1;
+                let bottom = (params.get(1, lines as u16) as usize).min(lines);
+                if top + 1 < bottom {
+                    self.margins.top = top;
+                    self.margins.bottom = bottom;
+                    self.goto_origin(Some(0), Some(0));
+                }
+            }
+            // CSI s is DECSLRM while left and right margins are enabled
+            (b"", b's') if self.lr_margin_mode => {
+                let left = params.get(0, 1) as usize - 1;
+                let right = (params.get(1, cols as u16) as usize).min(cols);
+                if left + 1 < right {
+                    self.margins.left = left;
+                    self.margins.right = right;
+                    self.goto_origin(Some(0), Some(0));
+                }
+            }
+            (b"", b's') => self.save_cursor(),
+            (b"", b'u') => self.restore_cursor(),
+            (b">", b'u') => {
+                if self.kitty_flags.len() == KITTY_STACK_LIMIT {
+                    self.kitty_flags.remove(0);
+                }
+                self.kitty_flags.push(kitty_flags(params.get(0, 0)));
+            }
+            (b"<", b'u') => {
+                let len = self.kitty_flags.len().saturating_sub(n);
+                self.kitty_flags.truncate(len);
+            }
+            (b"=", b'u') => {
+                let flags = kitty_flags(params.get(0, 0));
+                let current = self.key_modes().kitty_flags;
+                let flags = match params.get(1, 1) {
+                    1 => flags,
+                    2 => current | flags,
+                    3 => current - flags,
+                    _ => return,
+                };
+                match self.kitty_flags.last_mut() {
+                    Some(last) => *last = flags,
+                    None => self.kitty_flags.push(flags),
+                }
+            }
+            (b"?", b'u') => {
+                let flags = self.key_modes().kitty_flags.bits();
+                self.responses.push_str(&format!("\x1b[?{}u", flags));
+            }
+            (b"", b'n') => match params.get(0, 0) {
+                5 => self.responses.push_str("\x1b[0n"),
+                6 => {
+                    let (line, col) = self.cursor_position();
+                    self.responses
+                        .push_str(&format!("\x1b[{};{}R", line + 1, col + 1));
+                }
+                _ => (),
+            },
+            (b"?", b'n') if params.get(0, 0) == 6 => {
+                let (line, col) = self.cursor_position();
+                self.responses
+                    .push_str(&format!("\x1b[?{};{}R", line + 1, col + 1));
+            }
+            // primary DA, a VT220 with ANSI colour
+            (b"", b'c') if params.get(0, 0) == 0 => self.responses.push_str("\x1b[?62;22c"),
+            (b">", b'c') if params.get(0, 0) == 0 => {
+                let mut version = VERSION.split('.').map(|n| n.parse::<u32>().unwrap_or(0));
+                let mut next = || version.next().unwrap_or(0);
+                let number = next() * 10000 + next() * 100 + next();
+                self.responses.push_str(&format!("\x1b[>0;{};0c", number));
+            }
+            (b"=", b'c') if params.get(0, 0) == 0 => {
+                self.responses.push_str("\x1bP!|00000000\x1b\\")
+            }
+            (b">", b'q') if params.get(0, 0) == 0 => {
+                self.responses
+                    .push_str(&format!("\x1bP>|{}({})\x1b\\", NAME, VERSION));
+            }
+            // DECRQM, 1 is set, 2 reset, 3 and 4 permanently set and reset
+            // and 0 an unknown mode
+            (b"$", b'p') => {
+                let mode = params.get(0, 0);
+                let state = ansi_mode(mode);
+                self.responses
+                    .push_str(&format!("\x1b[{};{}$y", mode, state));
+            }
+            (b"?$", b'p') => {
+                let mode = params.get(0, 0);
+                let state = match self.private_mode(mode) {
+                    Some(enabled) => 2 - enabled as u8,
+                    None => 0,
+                };
+                self.responses
+                    .push_str(&format!("\x1b[?{};{}$y", mode, state));
+            }
+            // XTWINOPS, pushing and popping titles and size reports
+            (b"", b't') => match params.get(0, 0) {
+                14 => {
+                    let (width, height) = (cols * self.cell_width, lines * self.cell_height);
+                    self.responses
+                        .push_str(&format!("\x1b[4;{};{}t", height, width));
+                }
+                16 => {
+                    let (width, height) = (self.cell_width, self.cell_height);
+                    self.responses
+                        .push_str(&format!("\x1b[6;{};{}t", height, width));
+                }
+                18 => self
+                    .responses
+                    .push_str(&format!("\x1b[8;{};{}t", lines, cols)),
+                22 if self.title_stack.len() < TITLE_STACK_LIMIT => {
+                    self.title_stack.push(self.title.clone());
+                }
+                23 => {
+                    // the second parameter picks the icon name (1), the
+                    // title (2) or both (0)
+                    if let Some(title) = self.title_stack.pop() {
+                        let which = params.iter().nth(1).map_or(0, |param| param[0]);
+                        if which != 2 {
+                            self.title.icon_name = title.icon_name;
+                        }
+                        if which != 1 {
+                            self.set_title(title.title);
+                        }
+                    }
+                }
+                _ => (),
+            },
+            (b"", b'b') => {
+                if let Some(c) = self.last_printed {
+                    for _ in 0..n {
+                        self.print(c);
+                    }
+                }
+            }
+            (b"?", b'h') | (b"?", b'l') => {
+                for mode in params.iter() {
+                    self.set_private_mode(mode[0], byte == b'h');
+                }
+            }
+            _ => (),
+        }
+    }
+    fn sgr(&mut self, params: &Params) {
+        let template = &mut self.cursor.template;
+        if params.is_empty() {
+            *template = Cell::default();
+            return;
+        }
+        let mut params = params.iter();
+        while let Some(param) = params.next() {
+            match param[0] {
+                0 => *template = Cell::default(),
+                1 => template.flags.insert(Flags::BOLD),
+                2 => template.flags.insert(Flags::DIM),
+                3 => template.flags.insert(Flags::ITALIC),
+                4 => {
+                    template.flags.remove(Flags::ALL_UNDERLINES);
+                    template.flags.insert(match param.get(1) {
+                        None | Some(1) => Flags::UNDERLINE,
+                        Some(2) => Flags::DOUBLE_UNDERLINE,
+                        Some(3) => Flags::CURLY_UNDERLINE,
+                        Some(4) => Flags::DOTTED_UNDERLINE,
+                        Some(5) => Flags::DASHED_UNDERLINE,
+                        _ => Flags::empty(),
+                    });
+                }
+                5 | 6 => template.flags.insert(Flags::BLINK),
+                7 => template.flags.insert(Flags::INVERSE),
+                8 => template.flags.insert(Flags::HIDDEN),
+                9 => template.flags.insert(Flags::STRIKEOUT),
+                21 => {
+                    template.flags.remove(Flags::ALL_UNDERLINES);
+                    template.flags.insert(Flags::DOUBLE_UNDERLINE);
+                }
+                22 => template.flags.remove(Flags::BOLD | Flags::DIM),
+                23 => template.flags.remove(Flags::ITALIC),
+                24 => template.flags.remove(Flags::ALL_UNDERLINES),
+                25 => template.flags.remove(Flags::BLINK),
+                27 => template.flags.remove(Flags::INVERSE),
+                28 => template.flags.remove(Flags::HIDDEN),
+                29 => template.flags.remove(Flags::STRIKEOUT),
+                53 => template.flags.insert(Flags::OVERLINE),
+                55 => template.flags.remove(Flags::OVERLINE),
+                30..=37 => template.fg = Color::Indexed(param[0] as u8 - 30),
+                39 => template.fg = Color::Default,
+                40..=47 => template.bg = Color::Indexed(param[0] as u8 - 40),
+                49 => template.bg = Color::Default,
+                59 => template.underline_color = Color::Default,
+                90..=97 => template.fg = Color::Indexed(param[0] as u8 - 90 + 8),
+                100..=107 => template.bg = Color::Indexed(param[0] as u8 - 100 + 8),
+                38 | 48 | 58 => {
+                    let color = if param.len() > 1 {
+                        extended_color(&param[1..])
+                    } else {
+                        // the semicolon form takes its values from the following parameters
+                        match params.next().map(|param| param[0]) {
+                            Some(5) => params
+                                .next()
+                                .and_then(|index| extended_color(&[5, index[0]])),
+                            Some(2) => {
+                                let mut channel = || params.next().map(|p| p[0]).unwrap_or(0);
+                                let (r, g, b) = (channel(), channel(), channel());
+                                extended_color(&[2, r, g, b])
+                            }
+                            _ => None,
+                        }
+                    };
+                    if let Some(color) = color {
+                        match param[0] {
+                            38 => template.fg = color,
+                            48 => template.bg = color,
+                            _ => template.underline_color = color,
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+    fn set_private_mode(&mut self, mode: u16, enabled: bool) {
+        match mode {
+            1 => self.key_modes.app_cursor = enabled,
+            6 => {
+                self.origin_mode = enabled;
+                self.goto_origin(Some(0), Some(0));
+            }
+            7 => self.autowrap = enabled,
+            47 | 1047 if enabled != self.alt_screen => {
+                // 1047 leaves the alternate screen cleared for next time
+                if !enabled && mode == 1047 {
+                    self.clear_screen();
+                }
+                self.swap_screens();
+            }
+            1048 if enabled => self.save_cursor(),
+            1048 => self.restore_cursor(),
+            1049 if enabled && !self.alt_screen => {
+                self.save_cursor();
+                self.swap_screens();
+                self.clear_screen();
+            }
+            1049 if !enabled && self.alt_screen => {
+                self.swap_screens();
+                self.restore_cursor();
+            }
+            9 | 1000 | 1002 | 1003 => {
+                self.mouse_modes.mode = match mode {
+                    _ if !enabled => MouseMode::Off,
+                    9 => MouseMode::Press,
+                    1000 => MouseMode::PressRelease,
+                    1002 => MouseMode::ButtonMotion,
+                    _ => MouseMode::AnyMotion,
+                }
+            }
+            1005 | 1006 | 1015 | 1016 => {
+                self.mouse_modes.encoding = match mode {
+                    _ if !enabled => MouseEncoding::X10,
+                    1005 => MouseEncoding::Utf8,
+                    1006 => MouseEncoding::Sgr,
+                    1015 => MouseEncoding::Urxvt,
+                    _ => MouseEncoding::SgrPixels,
+                }
+            }
+            2004 => self.bracketed_paste = enabled,
+            1007 => self.alternate_scroll = enabled,
+            69 => {
+                self.lr_margin_mode = enabled;
+                if !enabled {
+                    self.margins.left = 0;
+                    self.margins.right = self.grid.cols();
+                }
+            }
+            _ => (),
+        }
+    }
+    // the state of a DEC private mode for DECRQM, None for unknown modes
+    fn private_mode(&self, mode: u16) -> Option<bool> {
+        let mouse = self.mouse_modes;
+        Some(match mode {
+            1 => self.key_modes.app_cursor,
+            6 => self.origin_mode,
+            7 => self.autowrap,
+            47 | 1047 | 1049 => self.alt_screen,
+            // set while there is a saved cursor to restore
+            1048 => self.saved_cursor.is_some(),
+            69 => self.lr_margin_mode,
+            9 => mouse.mode == MouseMode::Press,
+            1000 => mouse.mode == MouseMode::PressRelease,
+            1002 => mouse.mode == MouseMode::ButtonMotion,
+            1003 => mouse.mode == MouseMode::AnyMotion,
+            1005 => mouse.encoding == MouseEncoding::Utf8,
+            1006 => mouse.encoding == MouseEncoding::Sgr,
+            1015 => mouse.encoding == MouseEncoding::Urxvt,
+            1016 => mouse.encoding == MouseEncoding::SgrPixels,
+            2004 => self.bracketed_paste,
+            1007 => self.alternate_scroll,
+            _ => return None,
+        })
+    }
+    // the cursor as CUP would set it, relative to the margins in origin mode
+    fn cursor_position(&self) -> (usize, usize) {
+        let (line, col) = (self.cursor.line, self.cursor.col);
+        if self.origin_mode {
+            // DECRC can put the cursor above or left of the margins
+            (
+                line.saturating_sub(self.margins.top),
+                col.saturating_sub(self.margins.left),
+            )
+        } else {
+            (line, col)
+        }
+    }
+    fn swap_screens(&mut self) {
+        self.display_offset = 0;
+        std::mem::swap(&mut self.grid, &mut self.inactive_grid);
+        std::mem::swap(&mut self.saved_cursor, &mut self.inactive_saved_cursor);
+        std::mem::swap(&mut self.kitty_flags, &mut self.inactive_kitty_flags);
+        self.alt_screen = !self.alt_screen;
+    }
+    fn clear_screen(&mut self) {
+        let blank = self.blank();
+        let cols = self.grid.cols();
+        for line in 0..self.grid.lines() {
+            self.grid.row_mut(line).erase(0, cols, blank);
+            self.grid.row_mut(line).set_wrapped(false);
+        }
+    }
+    // blank cells keep the current background colour
+    fn blank(&self) -> Cell {
+        Cell {
+            bg: self.cursor.template.bg,
+            ..Cell::default()
+        }
+    }
+    fn goto(&mut self, line: usize, col: usize) {
+        self.cursor.line = line.min(self.grid.lines() - 1);
+        self.cursor.col = col.min(self.grid.cols() - 1);
+        self.cursor.pending_wrap = false;
+    }
+    // absolute positioning, relative to the margins in origin mode. None keeps
+    // the current line or column.
+    fn goto_origin(&mut self, line: Option<usize>, col: Option<usize>) {
+        let region = if self.origin_mode {
+            self.margins
+        } else {
+            self.grid.full_region()
+        };
+        let line = line
+            .map(|line| (region.top + line).min(region.bottom - 1))
+            .unwrap_or(self.cursor.line);
+        let col = col
+            .map(|col| (region.left + col).min(region.right - 1))
+            .unwrap_or(self.cursor.col);
+        self.goto(line, col);
+    }
+    fn in_margins(&self, line: usize, col: usize) -> bool {
+        let margins = self.margins;
+        (margins.top..margins.bottom).contains(&line)
+            && (margins.left..margins.right).contains(&col)
+    }
+    fn save_cursor(&mut self) {
+        self.saved_cursor = Some(SavedCursor {
+            cursor: self.cursor,
+            origin_mode: self.origin_mode,
+        });
+    }
+    fn restore_cursor(&mut self) {
+        let saved = self.saved_cursor.unwrap_or_default();
+        self.cursor = saved.cursor;
+        self.origin_mode = saved.origin_mode;
+        self.goto(self.cursor.line, self.cursor.col);
+    }
+    fn erase_display(&mut self, mode: u16) {
+        let blank = self.blank();
+        let (line, col) = (self.cursor.line, self.cursor.col);
+        let (cols, lines) = (self.grid.cols(), self.grid.lines());
+        match mode {
+            0 => {
+                self.grid.row_mut(line).erase(col, cols, blank);
+                for line in line..lines {
+                    self.grid.row_mut(line).set_wrapped(false);
+                }
+                for line in line + 1..lines {
+                    self.grid.row_mut(line).erase(0, cols, blank);
+                }
+            }
+            1 => {
+                for line in 0..line {
+                    self.grid.row_mut(line).erase(0, cols, blank);
+                    self.grid.row_mut(line).set_wrapped(false);
+                }
+                self.grid.row_mut(line).erase(0, col + 1, blank);
+            }
+            2 => self.clear_screen(),
+            3 => {
+                self.scrollback.clear();
+                self.display_offset = 0;
+            }
+            _ => (),
+        }
+    }
+    fn erase_line(&mut self, mode: u16) {
+        let blank = self.blank();
+        let (line, col) = (self.cursor.line, self.cursor.col);
+        let cols = self.grid.cols();
+        match mode {
+            0 => self.grid.row_mut(line).erase(col, cols, blank),
+            1 => return self.grid.row_mut(line).erase(0, col + 1, blank),
+            2 => self.grid.row_mut(line).erase(0, cols, blank),
+            _ => return,
+        }
+        // the end of the line was erased, it no longer runs into the next one
+        self.grid.row_mut(line).set_wrapped(false);
+    }
+    fn carriage_return(&mut self) {
+        self.cursor.col = if self.cursor.col >= self.margins.left {
+            self.margins.left
+        } else {
+            0
+        };
+        self.cursor.pending_wrap = false;
+    }
+    fn linefeed(&mut self) {
+        let (line, col) = (self.cursor.line, self.cursor.col);
+        if line + 1 == self.margins.bottom && self.in_margins(line, col) {
+            self.scroll_up(1);
+        } else if line + 1 < self.grid.lines() {
+            self.cursor.line += 1;
+        }
+        self.cursor.pending_wrap = false;
+    }
+    fn reverse_index(&mut self) {
+        let (line, col) = (self.cursor.line, self.cursor.col);
+        if line == self.margins.top && self.in_margins(line, col) {
+            self.scroll_down(1);
+        } else if line > 0 {
+            self.cursor.line -= 1;
+        }
+        self.cursor.pending_wrap = false;
+    }
+    fn scroll_up(&mut self, count: usize) {
+        let blank = self.blank();
+        let removed = self.grid.scroll_up(self.margins, count, blank);
+        // only lines leaving the top of the primary screen are kept
+        if !self.alt_screen && self.margins.top == 0 {
+            for row in removed {
+                self.push_scrollback(row);
+            }
+        }
+    }
+    fn push_scrollback(&mut self, row: Row) {
+        self.scrollback.push(row);
+        self.scrolled += 1;
+        // a scrolled back view stays on the same lines
+        if self.display_offset > 0 {
+            self.display_offset = (self.display_offset + 1).min(self.scrollback.len());
+        }
+    }
+    fn scroll_down(&mut self, count: usize) {
+        let blank = self.blank();
+        self.grid.scroll_down(self.margins, count, blank);
+    }
+}
+// Joins the rows of the scrollback and the screen that were soft wrapped back
+// into lines and wraps them again at cols. The screen keeps the bottom lines
+// and the cursor, which moves along with the text it was on.
+fn reflow(
+    scrollback: &mut Scrollback,
+    grid: &mut Grid,
+    cursor: &mut Cursor,
+    cols: usize,
+    lines: usize,
+) {
+    let mut rows = scrollback.take();
+    let cursor_row = rows.len() + cursor.line;
+    rows.extend(std::mem::replace(grid, Grid::new(cols, 0)).into_rows());
+    // each line with the offset of the cursor in it
+    let mut logical: Vec<Vec<Cell>> = vec![];
+    let mut cursor_at = (0, 0);
+    let mut current = vec![];
+    for (index, row) in rows.into_iter().enumerate() {
+        if index == cursor_row {
+            // a pending wrap means the cursor is really after the last column
+            let col = cursor.col + cursor.pending_wrap as usize;
+            cursor_at = (logical.len(), current.len() + col);
+        }
+        let wrapped = row.wrapped();
+        current.extend(row.into_cells());
+        if !wrapped {
+            logical.push(std::mem::take(&mut current));
+        }
+    }
+    if !current.is_empty() {
+        logical.push(current);
+    }
+    let mut rows = vec![];
+    let mut cursor_line = 0;
+    for (index, mut cells) in logical.into_iter().enumerate() {
+        let len = cells
+            .iter()
+            .rposition(|cell| *cell != Cell::default())
+            .map_or(0, |last| last + 1);
+        cells.truncate(len);
+        let mut count = len.div_ceil(cols).max(1);
+        if index == cursor_at.0 {
+            let mut offset = cursor_at.1;
+            // right after text ending at the edge, wait to wrap on the last column
+            cursor.pending_wrap = offset > 0 && offset % cols == 0 && offset >= len;
+            if cursor.pending_wrap {
+                offset -= 1;
+            }
+            count = count.max(offset / cols + 1);
+            cursor_line = rows.len() + offset / cols;
+            cursor.col = offset % cols;
+        }
+        for i in 0..count {
+            let chunk = cells[(i * cols).min(len)..((i + 1) * cols).min(len)].to_vec();
+            let mut row = Row::from_cells(chunk, i + 1 < count);
+            row.resize(cols);
+            rows.push(row);
+        }
+    }
+    // blank lines below the cursor are not worth keeping on a smaller screen
+    while rows.len() > cursor_line + 1 && rows.last() == Some(&Row::new(cols, Cell::default())) {
+        rows.pop();
+    }
+    // rows above the screen go into the scrollback, the cursor's line always
+    // stays on screen so rows far enough below it are cut off
+    let start = rows.len().saturating_sub(lines).min(cursor_line);
+    let mut screen = rows.split_off(start);
+    screen.resize(lines, Row::new(cols, Cell::default()));
+    for row in rows {
+        scrollback.push(row);
+    }
+    *grid = Grid::from_rows(cols, screen);
+    cursor.line = cursor_line - start;
+}
+// unsupported flags are dropped so queries report what is really used
+fn kitty_flags(value: u16) -> KittyFlags {
+    KittyFlags::from_bits_truncate(value.min(255) as u8)
+}
+// The DECRQM state of an ANSI mode. None can be changed, those below are
+// reported for what the terminal always does.
+fn ansi_mode(mode: u16) -> u8 {
+    match mode {
+        // KAM, the keyboard is never locked
+        2 => 4,
+        // IRM, printing replaces characters instead of inserting
+        4 => 4,
+        // SRM, typed characters are not echoed locally
+        12 => 3,
+        // LNM, a line feed does not return the carriage
+        20 => 4,
+        _ => 0,
+    }
+}
+// the colon form of an extended colour: 5:index, 2:r:g:b or 2:colour space:r:g:b
+fn extended_color(params: &[u16]) -> Option<Color> {
+    let channel = |value: u16| value.min(255) as u8;
+    match params {
+        [5, index, ..] => Some(Color::Indexed(channel(*index))),
+        [2, _, r, g, b, ..] | [2, r, g, b] => {
+            Some(Color::Rgb(channel(*r), channel(*g), channel(*b)))
+        }
+        _ => None,
+    }
+}
+// the SGR parameters that give a cell's colours and attributes
+fn sgr_params(cell: &Cell) -> String {
+    let mut params = vec!["0".to_string()];
+    let flags = [
+        (Flags::BOLD, "1"),
+        (Flags::DIM, "2"),
+        (Flags::ITALIC, "3"),
+        (Flags::UNDERLINE, "4"),
+        (Flags::DOUBLE_UNDERLINE, "21"),
+        (Flags::CURLY_UNDERLINE, "4:3"),
+        (Flags::DOTTED_UNDERLINE, "4:4"),
+        (Flags::DASHED_UNDERLINE, "4:5"),
+        (Flags::BLINK, "5"),
+        (Flags::INVERSE, "7"),
+        (Flags::HIDDEN, "8"),
+        (Flags::STRIKEOUT, "9"),
+        (Flags::OVERLINE, "53"),
+    ];
+    for (flag, param) in flags.iter() {
+        if cell.flags.contains(*flag) {
+            params.push(param.to_string());
+        }
+    }
+    let colors = [(cell.fg, 30), (cell.bg, 40), (cell.underline_color, 50)];
+    for &(color, base) in colors.iter() {
+        match color {
+            Color::Default => continue,
+            // underline colours only have the extended form
+            Color::Indexed(index) if index < 8 && base != 50 => {
+                params.push((base + index as u16).to_string())
+            }
+            Color::Indexed(index) if index < 16 && base != 50 => {
+                params.push((base + 60 + index as u16 - 8).to_string())
+            }
+            Color::Indexed(index) => params.push(format!("{};5;{}", base + 8, index)),
+            Color::Rgb(r, g, b) => params.push(format!("{};2;{};{};{}", base + 8, r, g, b)),
+        }
+    }
+    params.join(";")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen(term: &Term) -> Vec<String> {
+        term.grid()
+            .rows()
+            .iter()
+            .map(|row| row.text().trim_end().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn newline_and_carriage_return() {
+        let mut term = Term::new(10, 3, 0);
+        term.advance(b"ab\r\ncd\nef");
+        assert_eq!(screen(&term), vec!["ab", "cd", "  ef"]);
+        assert_eq!((term.cursor().line, term.cursor().col), (2, 4));
+    }
+    #[test]
+    fn backspace_and_tab() {
+        let mut term = Term::new(20, 1, 0);
+        term.advance(b"abc\x08\x08x\ty");
+        assert_eq!(screen(&term), vec!["axc     y"]);
+    }
+    #[test]
+    fn autowrap_is_deferred() {
+        let mut term = Term::new(4, 3, 0);
+        term.advance(b"abcd");
+        assert_eq!((term.cursor().line, term.cursor().col), (0, 3));
+        term.advance(b"e");
+        assert_eq!(screen(&term), vec!["abcd", "e", ""]);
+        term.advance(b"\x1b[?7lfghij");
+        assert_eq!(screen(&term), vec!["abcd", "efgj", ""]);
+    }
+    #[test]
+    fn scrolls_at_bottom() {
+        let mut term = Term::new(4, 2, 0);
+        term.advance(b"1\r\n2\r\n3");
+        assert_eq!(screen(&term), vec!["2", "3"]);
+    }
+    #[test]
+    fn relative_cursor_movement() {
+        let mut term = Term::new(10, 5, 0);
+        term.advance(b"\x1b[3;4Ha\x1b[2Ab\x1b[Bc\x1b[3Dd\x1b[5Ce");
+        assert_eq!(screen(&term), vec!["    b", "   d c   e", "   a", "", ""]);
+    }
+    #[test]
+    fn absolute_cursor_movement() {
+        let mut term = Term::new(10, 5, 0);
+        term.advance(b"\x1b[99;99Hx\x1b[2dy\x1b[3Gz\x1b[Hw");
+        assert_eq!(screen(&term), vec!["w", "  z      y", "", "", "         x"]);
+        term.advance(b"\x1b[5;2fv\x1b[1`u");
+        assert_eq!(screen(&term)[4], "uv       x");
+    }
+    #[test]
+    fn erase_display() {
+        let fill = b"abc\r\ndef\r\nghi\x1b[2;2H";
+        let mut term = Term::new(3, 3, 0);
+        term.advance(fill);
+        term.advance(b"\x1b[J");
+        assert_eq!(screen(&term), vec!["abc", "d", ""]);
+        term.advance(b"\x1b[H");
+        term.advance(fill);
+        term.advance(b"\x1b[1J");
+        assert_eq!(screen(&term), vec!["", "  f", "ghi"]);
+        term.advance(b"\x1b[2J");
+        assert_eq!(screen(&term), vec!["", "", ""]);
+        assert_eq!((term.cursor().line, term.cursor().col), (1, 1));
+    }
+    #[test]
+    fn erase_line() {
+        let mut term = Term::new(5, 1, 0);
+        term.advance(b"abcde\x1b[3G\x1b[K");
+        assert_eq!(screen(&term), vec!["ab"]);
+        term.advance(b"\rabcde\x1b[3G\x1b[1K");
+        assert_eq!(screen(&term), vec!["   de"]);
+        term.advance(b"\x1b[2K");
+        assert_eq!(screen(&term), vec![""]);
+    }
+    #[test]
+    fn insert_delete_and_erase_characters() {
+        let mut term = Term::new(8, 1, 0);
+        term.advance(b"abcdefgh\x1b[2G\x1b[2@");
+        assert_eq!(screen(&term), vec!["a  bcdef"]);
+        term.advance(b"\x1b[3P");
+        assert_eq!(screen(&term), vec!["acdef"]);
+        term.advance(b"\x1b[2X");
+        assert_eq!(screen(&term), vec!["a  ef"]);
+        term.advance(b"\x1b[99@");
+        assert_eq!(screen(&term), vec!["a"]);
+    }
+    #[test]
+    fn insert_and_delete_lines() {
+        let mut term = Term::new(3, 4, 0);
+        term.advance(b"1\r\n2\r\n3\r\n4\x1b[2;2H\x1b[L");
+        assert_eq!(screen(&term), vec!["1", "", "2", "3"]);
+        assert_eq!((term.cursor().line, term.cursor().col), (1, 0));
+        term.advance(b"\x1b[2M");
+        assert_eq!(screen(&term), vec!["1", "3", "", ""]);
+    }
+    #[test]
+    fn scroll_up_and_down() {
+        let mut term = Term::new(3, 3, 0);
+        term.advance(b"1\r\n2\r\n3\x1b[S");
+        assert_eq!(screen(&term), vec!["2", "3", ""]);
+        term.advance(b"\x1b[2T");
+        assert_eq!(screen(&term), vec!["", "", "2"]);
+    }
+    #[test]
+    fn save_and_restore_cursor() {
+        let mut term = Term::new(5, 3, 0);
+        term.advance(b"\x1b[2;3H\x1b7\x1b[Ha\x1b8b");
+        assert_eq!(screen(&term), vec!["a", "  b", ""]);
+        term.advance(b"\x1b[3;1H\x1b[s\x1b[Hc\x1b[ud");
+        assert_eq!(screen(&term), vec!["c", "  b", "d"]);
+    }
+    #[test]
+    fn repeat_last_character() {
+        let mut term = Term::new(6, 2, 0);
+        term.advance(b"ab\x1b[3b\x1b[b");
+        assert_eq!(screen(&term), vec!["abbbbb", ""]);
+    }
+    #[test]
+    fn sgr_attributes() {
+        let mut term = Term::new(4, 1, 0);
+        term.advance(b"\x1b[1;3;4:3;7ma\x1b[22;24mb\x1b[0;21;9;53mc\x1b[md");
+        let cells = term.grid().rows()[0].cells();
+        assert_eq!(
+            cells[0].flags,
+            Flags::BOLD | Flags::ITALIC | Flags::CURLY_UNDERLINE | Flags::INVERSE
+        );
+        assert_eq!(cells[1].flags, Flags::ITALIC | Flags::INVERSE);
+        assert_eq!(
+            cells[2].flags,
+            Flags::DOUBLE_UNDERLINE | Flags::STRIKEOUT | Flags::OVERLINE
+        );
+        assert_eq!(
+            cells[3],
+            Cell {
+                c: 'd',
+                ..Cell::default()
+            }
+        );
+    }
+    #[test]
+    fn sgr_colors() {
+        let mut term = Term::new(7, 1, 0);
+        term.advance(b"\x1b[31;102ma\x1b[38;5;200;48;2;1;2;3mb");
+        term.advance(b"\x1b[38:2::10:20:30;48:5:17mc\x1b[38:2:40:50:60md");
+        term.advance(b"\x1b[58;2;7;8;9;39;49me\x1b[59mf");
+        let cells = term.grid().rows()[0].cells();
+        assert_eq!(
+            (cells[0].fg, cells[0].bg),
+            (Color::Indexed(1), Color::Indexed(10))
+        );
+        assert_eq!(
+            (cells[1].fg, cells[1].bg),
+            (Color::Indexed(200), Color::Rgb(1, 2, 3))
+        );
+        assert_eq!(
+            (cells[2].fg, cells[2].bg),
+            (Color::Rgb(10, 20, 30), Color::Indexed(17))
+        );
+        assert_eq!(cells[3].fg, Color::Rgb(40, 50, 60));
+        assert_eq!((cells[4].fg, cells[4].bg), (Color::Default, Color::Default));
+        assert_eq!(cells[4].underline_color, Color::Rgb(7, 8, 9));
+        assert_eq!(cells[5].underline_color, Color::Default);
+        // out of range values are clamped rather than wrapped
+        term.advance(b"\x1b[38;5;256;48;2;300;0;0mg");
+        let cells = term.grid().rows()[0].cells();
+        assert_eq!(
+            (cells[6].fg, cells[6].bg),
+            (Color::Indexed(255), Color::Rgb(255, 0, 0))
+        );
+    }
+    #[test]
+    fn scroll_region() {
+        let mut term = Term::new(3, 5, 0);
+        term.advance(b"1\r\n2\r\n3\r\n4\r\n5\x1b[2;4r");
+        assert_eq!((term.cursor().line, term.cursor().col), (0, 0));
+        term.advance(b"\x1b[4;1H\nx");
+        assert_eq!(screen(&term), vec!["1", "3", "4", "x", "5"]);
+        term.advance(b"\x1b[5;1H\n\ny");
+        assert_eq!(screen(&term), vec!["1", "3", "4", "x", "y"]);
+        term.advance(b"\x1b[2;1H\x1bMz");
+        assert_eq!(screen(&term), vec!["1", "z", "3", "4", "y"]);
+        term.advance(b"\x1b[S");
+        assert_eq!(screen(&term), vec!["1", "3", "4", "", "y"]);
+        term.advance(b"\x1b[2T");
+        assert_eq!(screen(&term), vec!["1", "", "", "3", "y"]);
+    }
+    #[test]
+    fn insert_and_delete_lines_in_region() {
+        let mut term = Term::new(3, 5, 0);
+        term.advance(b"1\r\n2\r\n3\r\n4\r\n5\x1b[2;4r\x1b[3;2H\x1b[L");
+        assert_eq!(screen(&term), vec!["1", "2", "", "3", "5"]);
+        assert_eq!((term.cursor().line, term.cursor().col), (2, 0));
+        term.advance(b"\x1b[2M");
+        assert_eq!(screen(&term), vec!["1", "2", "", "", "5"]);
+        term.advance(b"\x1b[5;1H\x1b[L");
+        assert_eq!(screen(&term), vec!["1", "2", "", "", "5"]);
+    }
+    #[test]
+    fn origin_mode() {
+        let mut term = Term::new(5, 5, 0);
+        term.advance(b"\x1b[2;4r\x1b[?6h");
+        assert_eq!((term.cursor().line, term.cursor().col), (1, 0));
+        term.advance(b"\x1b[2;2Ha\x1b[9;1Hb\x1b7\x1b[?6l\x1b[Hc\x1b8\x1b[Hd");
+        assert_eq!(screen(&term), vec!["c", "d", " a", "b", ""]);
+    }
+    #[test]
+    fn index_and_next_line() {
+        let mut term = Term::new(4, 3, 0);
+        term.advance(b"ab\x1bDc\x1bEd");
+        assert_eq!(screen(&term), vec!["ab", "  c", "d"]);
+        term.advance("\u{85}e\u{8d}\u{8d}\u{8d}f".as_bytes());
+        assert_eq!(screen(&term), vec![" f", "  c", "d"]);
+    }
+    #[test]
+    fn left_and_right_margins() {
+        let mut term = Term::new(5, 3, 0);
+        // without DECLRMM this is a cursor save
+        term.advance(b"abcde\r\nfghij\r\nklmno\x1b[2;4s");
+        assert_eq!((term.cursor().line, term.cursor().col), (2, 4));
+        term.advance(b"\x1b[?69h\x1b[2;4s");
+        assert_eq!((term.cursor().line, term.cursor().col), (0, 0));
+        term.advance(b"\x1b[3;4H\n");
+        assert_eq!(screen(&term), vec!["aghie", "flmnj", "k   o"]);
+        term.advance(b"\x1b[1;3H\x1b[@");
+        assert_eq!(screen(&term), vec!["ag he", "flmnj", "k   o"]);
+        term.advance(b"\x1b[1;3HXYZ");
+        assert_eq!(screen(&term), vec!["agXYe", "fZmnj", "k   o"]);
+        term.advance(b"\x1b[1;2H\x1b[L");
+        assert_eq!(screen(&term), vec!["a   e", "fgXYj", "kZmno"]);
+    }
+    #[test]
+    fn alternate_screen_1049() {
+        let mut term = Term::new(4, 2, 0);
+        term.advance(b"ab\r\ncd\x1b[1;2H\x1b[?1049h");
+        assert_eq!(screen(&term), vec!["", ""]);
+        term.advance(b"\x1b[2;2Hxy\x1b7");
+        assert_eq!(screen(&term), vec!["", " xy"]);
+        term.advance(b"\x1b[?1049l");
+        assert_eq!(screen(&term), vec!["ab", "cd"]);
+        assert_eq!((term.cursor().line, term.cursor().col), (0, 1));
+        term.advance(b"\x1b[?1049h");
+        assert_eq!(screen(&term), vec!["", ""]);
+    }
+    #[test]
+    fn alternate_screen_47_and_1047() {
+        let mut term = Term::new(4, 2, 0);
+        term.advance(b"ab\x1b[?47hx\x1b[?47l");
+        assert_eq!(screen(&term), vec!["ab", ""]);
+        term.advance(b"\x1b[?47h");
+        assert_eq!(screen(&term), vec!["  x", ""]);
+        term.advance(b"\x1b[?1047l\x1b[?1047h");
+        assert_eq!(screen(&term), vec!["", ""]);
+    }
+    fn scrollback_text(term: &Term) -> Vec<String> {
+        (0..term.scrollback.len())
+            .rev()
+            .map(|index| term.scrollback.get(index).text())
+            .collect()
+    }
+    #[test]
+    fn scrollback_keeps_lines_up_to_limit() {
+        let mut term = Term::new(4, 2, 2);
+        term.advance(b"1\r\n2\r\n3\r\n4\r\n5");
+        assert_eq!(scrollback_text(&term), vec!["2", "3"]);
+        assert_eq!(term.scrollback.get(0).cells().len(), 1);
+        term.advance(b"\x1b[3J");
+        assert!(term.scrollback.is_empty());
+    }
+    #[test]
+    fn scrollback_ignores_alternate_screen_and_regions() {
+        let mut term = Term::new(4, 3, 10);
+        term.advance(b"\x1b[?1049h1\r\n2\r\n3\r\n4\x1b[?1049l");
+        assert!(term.scrollback.is_empty());
+        term.advance(b"\x1b[2;3r\x1b[2;1H1\n2\n3");
+        assert!(term.scrollback.is_empty());
+    }
+    #[test]
+    fn display_offset() {
+        let mut term = Term::new(4, 2, 10);
+        term.advance(b"1\r\n2\r\n3");
+        term.scroll_display(5);
+        assert_eq!(term.display_offset(), 1);
+        assert_eq!(term.display_row(0).text(), "1");
+        assert_eq!(term.display_row(1).text(), "2   ");
+        term.advance(b"\r\n4");
+        assert_eq!(term.display_offset(), 2);
+        assert_eq!(term.display_row(0).text(), "1");
+        term.scroll_display(-5);
+        assert_eq!(term.display_row(0).text(), "3   ");
+        term.advance(b"\x1b[?1049h");
+        term.scroll_display(5);
+        assert_eq!(term.display_offset(), 0);
+    }
+    #[test]
+    fn reflow_narrower_and_back() {
+        let mut term = Term::new(6, 3, 10);
+        term.advance(b"abcdefgh\r\nij");
+        assert_eq!(screen(&term), vec!["abcdef", "gh", "ij"]);
+        term.resize(4, 2);
+        assert_eq!(scrollback_text(&term), vec!["abcd"]);
+        assert_eq!(screen(&term), vec!["efgh", "ij"]);
+        assert_eq!((term.cursor().line, term.cursor().col), (1, 2));
+        term.resize(10, 3);
+        assert!(term.scrollback.is_empty());
+        assert_eq!(screen(&term), vec!["abcdefgh", "ij", ""]);
+        assert_eq!((term.cursor().line, term.cursor().col), (1, 2));
+    }
+    #[test]
+    fn reflow_keeps_cursor_in_wrapped_line() {
+        let mut term = Term::new(4, 2, 10);
+        term.advance(b"abcdef\x1b[2;1H");
+        term.resize(2, 4);
+        assert_eq!(screen(&term), vec!["ab", "cd", "ef", ""]);
+        assert_eq!((term.cursor().line, term.cursor().col), (2, 0));
+    }
+    #[test]
+    fn reflow_does_not_join_hard_newlines() {
+        let mut term = Term::new(4, 2, 10);
+        term.advance(b"abcd\r\nef");
+        term.resize(8, 2);
+        assert_eq!(screen(&term), vec!["abcd", "ef"]);
+        term.advance(b"\x1b[H\x1b[Kxy");
+        term.resize(2, 2);
+        assert_eq!(screen(&term), vec!["xy", "ef"]);
+        term.advance(b"z");
+        assert_eq!(screen(&term), vec!["xy", "zf"]);
+    }
+    #[test]
+    fn reflow_keeps_cursor_line_on_screen() {
+        let mut term = Term::new(10, 4, 10);
+        term.advance(b"$ ls\r\na b\r\n$ \x1b[H");
+        term.resize(10, 2);
+        assert!(term.scrollback.is_empty());
+        assert_eq!(screen(&term), vec!["$ ls", "a b"]);
+        assert_eq!((term.cursor().line, term.cursor().col), (0, 0));
+        term.advance(b"x");
+        assert_eq!(screen(&term), vec!["x ls", "a b"]);
+    }
+    #[test]
+    fn kitty_keyboard_flags() {
+        let mut term = Term::new(4, 2, 0);
+        term.advance(b"\x1b[>1u\x1b[>11u\x1b[?u");
+        assert_eq!(term.take_responses(), "\x1b[?11u");
+        term.advance(b"\x1b[=2;3u\x1b[?u\x1b[<u\x1b[?u");
+        assert_eq!(term.take_responses(), "\x1b[?9u\x1b[?1u");
+        // the alternate screen keeps its own stack
+        term.advance(b"\x1b[?1049h\x1b[?u\x1b[>2u\x1b[?1049l\x1b[?u");
+        assert_eq!(term.take_responses(), "\x1b[?0u\x1b[?1u");
+        term.advance(b"\x1b[<5u\x1b[?u");
+        assert_eq!(term.take_responses(), "\x1b[?0u");
+    }
+    #[test]
+    fn modify_other_keys() {
+        let mut term = Term::new(4, 2, 0);
+        term.advance(b"\x1b[>4;2m");
+        assert!(term.key_modes().modify_other_keys);
+        term.advance(b"\x1b[>4m");
+        assert!(!term.key_modes().modify_other_keys);
+    }
+    #[test]
+    fn osc52_clipboard_requests() {
+        let mut term = Term::new(4, 2, 0);
+        term.advance(b"\x1b[c\x1b]52;c;aGVsbG8=\x07\x1b]52;p;?\x1b\\\x1b]52;;!!\x07");
+        assert_eq!(
+            term.take_clipboard_requests(),
+            vec![
+                ClipboardRequest::Store(ClipboardKind::Clipboard, "hello".to_string()),
+                ClipboardRequest::Load(ClipboardKind::Primary, "p".to_string(), 9),
+                ClipboardRequest::Store(ClipboardKind::Clipboard, String::new()),
+            ]
+        );
+    }
+    #[test]
+    fn titles() {
+        let mut term = Term::new(4, 2, 0);
+        assert!(term.take_title_changed());
+        term.advance(b"\x1b]0;vim\x07");
+        assert!(term.take_title_changed());
+        assert_eq!(term.title().title.as_deref(), Some("vim"));
+        assert_eq!(term.title().icon_name.as_deref(), Some("vim"));
+        term.advance(b"\x1b[22;0t\x1b]2;a;b\x1b\\\x1b]1;icon\x07");
+        assert_eq!(term.title().title.as_deref(), Some("a;b"));
+        term.advance(b"\x1b[23;2t");
+        assert!(term.take_title_changed());
+        assert_eq!(term.title().title.as_deref(), Some("vim"));
+        assert_eq!(term.title().icon_name.as_deref(), Some("icon"));
+        // popping an empty stack changes nothing
+        term.advance(b"\x1b[23;0t\x1b]2;vim\x07");
+        assert_eq!(term.title().icon_name.as_deref(), Some("icon"));
+        assert!(!term.take_title_changed());
+    }
+    fn responses(term: &mut Term, input: &[u8]) -> String {
+        term.advance(input);
+        term.take_responses()
+    }
+    #[test]
+    fn status_reports() {
+        let mut term = Term::new(10, 5, 0);
+        assert_eq!(responses(&mut term, b"\x1b[5n"), "\x1b[0n");
+        assert_eq!(responses(&mut term, b"\x1b[3;4H\x1b[6n"), "\x1b[3;4R");
+        // origin mode reports positions inside the margins
+        let input = b"\x1b[2;4r\x1b[?6h\x1b[2;3H\x1b[6n\x1b[?6n";
+        assert_eq!(responses(&mut term, input), "\x1b[2;3R\x1b[?2;3R");
+        // a cursor restored outside the margins is reported on their edge
+        let mut term = Term::new(10, 5, 0);
+        let input = b"\x1b[?6h\x1b7\x1b[3;5r\x1b8\x1b[6n";
+        assert_eq!(responses(&mut term, input), "\x1b[1;1R");
+    }
+    #[test]
+    fn device_attributes() {
+        let mut term = Term::new(10, 5, 0);
+        assert_eq!(
+            responses(&mut term, b"\x1b[c\x1b[0c"),
+            "\x1b[?62;22c\x1b[?62;22c"
+        );
+        let version: Vec<u32> = VERSION.split('.').map(|n| n.parse().unwrap()).collect();
+        let number = version[0] * 10000 + version[1] * 100 + version[2];
+        assert_eq!(
+            responses(&mut term, b"\x1b[>c"),
+            format!("\x1b[>0;{};0c", number)
+        );
+        assert_eq!(responses(&mut term, b"\x1b[=c"), "\x1bP!|00000000\x1b\\");
+        assert_eq!(
+            responses(&mut term, b"\x1b[>q"),
+            concat!("\x1bP>|new_term(", env!("CARGO_PKG_VERSION"), ")\x1b\\")
+        );
+    }
+    #[test]
+    fn mode_reports() {
+        let mut term = Term::new(10, 5, 0);
+        let input = b"\x1b[?2004h\x1b[?2004$p\x1b[?1$p\x1b[?1234$p\x1b[4$p\x1b[3$p";
+        assert_eq!(
+            responses(&mut term, input),
+            "\x1b[?2004;1$y\x1b[?1;2$y\x1b[?1234;0$y\x1b[4;4$y\x1b[3;0$y"
+        );
+        let input = b"\x1b[?1048$p\x1b[?1048h\x1b[?1048$p";
+        assert_eq!(responses(&mut term, input), "\x1b[?1048;2$y\x1b[?1048;1$y");
+        let input = b"\x1b[?1007$p\x1b[?1007h\x1b[?1007$p";
+        assert_eq!(responses(&mut term, input), "\x1b[?1007;2$y\x1b[?1007;1$y");
+    }
+    #[test]
+    fn size_reports() {
+        let mut term = Term::new(80, 24, 0);
+        term.set_cell_size(9, 18);
+        let input = b"\x1b[14t\x1b[16t\x1b[18t";
+        assert_eq!(
+            responses(&mut term, input),
+            "\x1b[4;432;720t\x1b[6;18;9t\x1b[8;24;80t"
+        );
+    }
+    #[test]
+    fn setting_reports() {
+        let mut term = Term::new(10, 5, 0);
+        let input = b"\x1b[1;4:3;31;48;5;200;58;2;1;2;3m\x1bP$qm\x1b\\";
+        assert_eq!(
+            responses(&mut term, input),
+            "\x1bP1$r0;1;4:3;31;48;5;200;58;2;1;2;3m\x1b\\"
+        );
+        let input = b"\x1b[2;4r\x1bP$qr\x1b\\\x1bP$qx\x1b\\";
+        assert_eq!(
+            responses(&mut term, input),
+            "\x1bP1$r2;4r\x1b\\\x1bP0$r\x1b\\"
+        );
+    }
+}
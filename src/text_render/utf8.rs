@@ -0,0 +1,91 @@
+use std::str;
+
+// Decodes UTF-8 that arrives in arbitrary chunks. A character split across two
+// chunks is held back until the rest of it arrives, invalid input becomes
+// U+FFFD.
+#[derive(Default)]
+pub struct Utf8Decoder {
+    pending: Vec<u8>,
+}
+impl Utf8Decoder {
+    pub fn new() -> Utf8Decoder {
+        Utf8Decoder::default()
+    }
+    pub fn decode(&mut self, bytes: &[u8], out: &mut String) {
+        let mut input = std::mem::take(&mut self.pending);
+        input.extend_from_slice(bytes);
+        let mut rest = &input[..];
+        loop {
+            match str::from_utf8(rest) {
+                Ok(valid) => {
+                    out.push_str(valid);
+                    return;
+                }
+                Err(e) => {
+                    let (valid, after) = rest.split_at(e.valid_up_to());
+                    out.push_str(unsafe { str::from_utf8_unchecked(valid) });
+                    match e.error_len() {
+                        Some(len) => {
+                            out.push(char::REPLACEMENT_CHARACTER);
+                            rest = &after[len..];
+                        }
+                        None => {
+                            // the input ends in the middle of a character
+                            self.pending = after.to_vec();
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // decodes the input in two chunks split at `at`
+    fn split_decode(bytes: &[u8], at: usize) -> String {
+        let mut decoder = Utf8Decoder::new();
+        let mut out = String::new();
+        decoder.decode(&bytes[..at], &mut out);
+        decoder.decode(&bytes[at..], &mut out);
+        out
+    }
+    #[test]
+    fn split_sequences() {
+        for text in ["é", "€", "😀", "a€b😀c"].iter() {
+            let bytes = text.as_bytes();
+            for at in 0..=bytes.len() {
+                assert_eq!(split_decode(bytes, at), *text, "split at {}", at);
+            }
+        }
+    }
+    #[test]
+    fn byte_at_a_time() {
+        let mut decoder = Utf8Decoder::new();
+        let mut out = String::new();
+        for &byte in "x😀€é".as_bytes() {
+            decoder.decode(&[byte], &mut out);
+        }
+        assert_eq!(out, "x😀€é");
+    }
+    #[test]
+    fn invalid_bytes() {
+        let mut decoder = Utf8Decoder::new();
+        let mut out = String::new();
+        decoder.decode(b"a\xffb\x80c", &mut out);
+        assert_eq!(out, "a\u{fffd}b\u{fffd}c");
+    }
+    #[test]
+    fn truncated_sequence_then_ascii() {
+        let mut decoder = Utf8Decoder::new();
+        let mut out = String::new();
+        // the first two bytes of a three byte character
+        decoder.decode(b"a\xe2\x82", &mut out);
+        assert_eq!(out, "a");
+        decoder.decode(b"bc", &mut out);
+        assert_eq!(out, "a\u{fffd}bc");
+    }
+}